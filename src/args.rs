@@ -1,6 +1,9 @@
 use crate::config::{Config, DEFAULT_FILENAME};
+use crate::utils::prompt;
 use anyhow::{anyhow, Result};
 use clap::{Parser, ValueEnum};
+use std::io::IsTerminal;
+use std::path::PathBuf;
 
 #[derive(ValueEnum, Clone, Debug)]
 pub enum Action {
@@ -8,6 +11,38 @@ pub enum Action {
     Remove,
     Update,
     List,
+    Restore,
+    Status,
+    Autoremove,
+    Tree,
+    Why,
+    Graph,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+    Dot,
+}
+
+/// What to do with a destination that already exists
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ConflictPolicy {
+    Overwrite,
+    Skip,
+    Backup,
+    Fail,
+}
+
+/// Shell loading the env script setting PATH and the variables of the tools
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Nu,
+    Posix,
 }
 
 #[derive(Parser, Clone, Debug)]
@@ -16,6 +51,9 @@ pub struct Args {
     #[arg(value_enum)]
     pub action: Action,
 
+    /// Tool or group inspected by the tree and why actions
+    pub target: Option<String>,
+
     /// Provide config toml file configuration
     #[arg(short, long, default_value_t = String::from(DEFAULT_FILENAME))]
     pub config: String,
@@ -47,6 +85,65 @@ pub struct Args {
     /// except the editor configuration works
     #[arg(long, default_value_t = false)]
     pub except_editor: bool,
+
+    /// Print what would be done without modifying anything
+    #[arg(long, default_value_t = false)]
+    pub dry_run: bool,
+
+    /// Answer yes to every question
+    #[arg(short, long, default_value_t = false)]
+    pub yes: bool,
+
+    /// Answer no to every question
+    #[arg(short, long, default_value_t = false)]
+    pub no: bool,
+
+    /// What to do when a file to install or remove is already there, instead of asking
+    #[arg(long, value_enum)]
+    pub on_conflict: Option<ConflictPolicy>,
+
+    /// Also remove installed tools depending on the removed ones
+    #[arg(long, default_value_t = false)]
+    pub cascade: bool,
+
+    /// Install under <PREFIX>/bin, <PREFIX>/etc and <PREFIX>/share
+    #[arg(long)]
+    pub prefix: Option<PathBuf>,
+
+    /// Directory binaries are installed in
+    #[arg(long)]
+    pub bin_dir: Option<PathBuf>,
+
+    /// Directory configurations are installed in
+    #[arg(long)]
+    pub config_dir: Option<PathBuf>,
+
+    /// Directory libraries, backups and the manifest are stored in
+    #[arg(long)]
+    pub data_dir: Option<PathBuf>,
+
+    /// Install for every user, in /usr/local/bin, /usr/local/lib and /etc/xdg, with the
+    /// manifest and backups in /var/lib/editor
+    #[arg(long)]
+    pub system: bool,
+
+    /// Also make the shell configuration source the generated env script
+    #[arg(long)]
+    pub edit_rc: bool,
+
+    /// Shell whose env script is suggested or sourced, guessed from $SHELL otherwise, posix
+    /// edits ~/.profile
+    #[arg(long, value_enum)]
+    pub shell: Option<Shell>,
+
+    /// Write everything under this directory instead of /, links still point to the
+    /// final locations
+    #[arg(long)]
+    pub root: Option<PathBuf>,
+
+    /// Output format, text or json for the list action and dot for the graph one
+    #[arg(long, value_enum)]
+    pub format: Option<Format>,
 }
 
 impl Args {
@@ -56,9 +153,70 @@ impl Args {
                 "You cannot provide 'only' and 'except' editor at once"
             ));
         }
+        if self.yes && self.no {
+            return Err(anyhow!("You cannot provide 'yes' and 'no' at once"));
+        }
+        if matches!(self.action, Action::Why) && self.target.is_none() {
+            return Err(anyhow!("The why action needs the tool to explain"));
+        }
+        if self.system
+            && (self.prefix.is_some()
+                || self.bin_dir.is_some()
+                || self.config_dir.is_some()
+                || self.data_dir.is_some())
+        {
+            return Err(anyhow!(
+                "You cannot provide 'system' with 'prefix', 'bin-dir', 'config-dir' or 'data-dir'"
+            ));
+        }
+        if self.format == Some(Format::Json) && !matches!(self.action, Action::List) {
+            return Err(anyhow!(
+                "The json format is only available for the list action"
+            ));
+        }
+        if self.format == Some(Format::Dot) && !matches!(self.action, Action::Graph) {
+            return Err(anyhow!(
+                "The dot format is only available for the graph action"
+            ));
+        }
+        if matches!(self.action, Action::Graph) && self.get_format() != Format::Dot {
+            return Err(anyhow!("The graph action only supports the dot format"));
+        }
         Ok(self)
     }
 
+    /// The given format, otherwise the one of the action
+    pub fn get_format(&self) -> Format {
+        self.format.unwrap_or(match self.action {
+            Action::Graph => Format::Dot,
+            _ => Format::Text,
+        })
+    }
+
+    /// false if questions cannot be asked to the user
+    pub fn is_interactive(&self) -> bool {
+        !self.yes && !self.no && std::io::stdin().is_terminal()
+    }
+
+    /// Ask a yes/no question, `default` is used when stdin is not a terminal
+    pub fn confirm(&self, message: &str, default: bool) -> Result<bool> {
+        let answer = if self.yes {
+            true
+        } else if self.no {
+            false
+        } else if !std::io::stdin().is_terminal() {
+            default
+        } else {
+            return Ok(match prompt(message)?.trim().to_lowercase().as_str() {
+                "y" | "yes" => true,
+                "n" | "no" => false,
+                _ => default,
+            });
+        };
+        println!("{message}{}", if answer { "y" } else { "n" });
+        Ok(answer)
+    }
+
     pub fn clone_with_everything(&self, config: &Config) -> Self {
         let mut args = self.clone();
 
@@ -0,0 +1,147 @@
+use crate::args::Args;
+use crate::transaction::Transaction;
+use crate::utils::{copy_path, format_timestamp, move_path, InstallDirs, BLUE, GREEN, NC};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const BACKUP_DIRNAME: &str = "editor-backups";
+pub const BACKUP_FILENAME: &str = "backup.toml";
+/// Backups of the editor are stored under this key
+pub const EDITOR_KEY: &str = "editor";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BackupEntry {
+    /// Where the file was before being replaced
+    pub original: PathBuf,
+    /// Relative to the backup directory
+    pub saved: PathBuf,
+}
+
+/// Files replaced during one run for one tool key
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Backup {
+    #[serde(skip)]
+    pub dir: PathBuf,
+    pub timestamp: u64,
+    pub files: Vec<BackupEntry>,
+}
+
+impl Backup {
+    pub fn get_root(dirs: &InstallDirs) -> PathBuf {
+        dirs.staged(dirs.state.join(BACKUP_DIRNAME))
+    }
+
+    /// Keys having at least one backup
+    pub fn keys(dirs: &InstallDirs) -> Result<Vec<String>> {
+        let root = Backup::get_root(dirs);
+        if !root.exists() {
+            return Ok(Vec::new());
+        }
+        let mut keys = Vec::new();
+        for entry in std::fs::read_dir(root)? {
+            let entry = entry?;
+            if entry.path().is_dir() {
+                keys.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// Backups of `key`, oldest first
+    pub fn list(dirs: &InstallDirs, key: &str) -> Result<Vec<Backup>> {
+        let dir = Backup::get_root(dirs).join(key);
+        let mut backups = Vec::new();
+        if dir.exists() {
+            for entry in std::fs::read_dir(dir)? {
+                let path = entry?.path().join(BACKUP_FILENAME);
+                if path.exists() {
+                    let mut backup: Backup = toml::from_str(&std::fs::read_to_string(&path)?)?;
+                    backup.dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+                    backups.push(backup);
+                }
+            }
+        }
+        backups.sort_by_key(|b| b.timestamp);
+        Ok(backups)
+    }
+
+    /// Move staged files (original, staged) into a new backup of `key`
+    pub(crate) fn create(
+        dirs: &InstallDirs,
+        key: &str,
+        files: Vec<(PathBuf, PathBuf)>,
+    ) -> Result<Backup> {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        let dir = Backup::get_root(dirs)
+            .join(key)
+            .join(format!("{timestamp}-{}", std::process::id()));
+        let mut backup = Backup {
+            dir,
+            timestamp,
+            files: Vec::with_capacity(files.len()),
+        };
+        for (index, (original, staged)) in files.into_iter().enumerate() {
+            let saved = PathBuf::from(index.to_string())
+                .join(original.file_name().unwrap_or(original.as_os_str()));
+            std::fs::create_dir_all(backup.dir.join(index.to_string()))?;
+            move_path(&staged, backup.dir.join(&saved))?;
+            backup.files.push(BackupEntry { original, saved });
+        }
+        std::fs::write(backup.dir.join(BACKUP_FILENAME), toml::to_string(&backup)?)?;
+        println!(
+            "{BLUE}BACKUP{NC}: Previous files of {GREEN}{key}{NC} saved in {}",
+            backup.dir.display()
+        );
+        Ok(backup)
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{} : {}",
+            format_timestamp(self.timestamp),
+            self.files
+                .iter()
+                .map(|f| f.original.display().to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+    }
+
+    /// Put the saved files back, backing up what is currently there
+    pub fn restore(&self, key: &str, args: &Args, tx: &mut Transaction) -> Result<()> {
+        tx.set_key(key);
+        for entry in &self.files {
+            let display = entry.original.display();
+            if args.dry_run {
+                println!("{BLUE}PLAN{NC}: restore {display}");
+                continue;
+            }
+            if entry.original.exists() || entry.original.is_symlink() {
+                tx.back_up(&entry.original)?;
+            }
+            if let Some(parent) = entry.original.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            tx.create(&entry.original, || {
+                copy_path(self.dir.join(&entry.saved), &entry.original)
+            })?;
+            println!("{GREEN}RESTORED{NC}: {display}");
+        }
+        Ok(())
+    }
+
+    pub fn delete(self) -> Result<()> {
+        std::fs::remove_dir_all(&self.dir)?;
+        if let Some(parent) = self.dir.parent() {
+            // remove the key directory once it has no backup left
+            if std::fs::read_dir(parent)?.next().is_none() {
+                std::fs::remove_dir(parent)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,16 +1,24 @@
-use crate::args::{Action, Args};
+use crate::args::{Action, Args, Format, Shell};
+use crate::backup::{Backup, EDITOR_KEY};
 use crate::dependencies::{Dependencies, DependencyErrorType, UnSatisfiedGroup, UnSatisfiedTool};
 use crate::editor::Editor;
+use crate::graph::Edge;
 use crate::group::Group;
+use crate::manifest::{Manifest, ManifestRecord};
 use crate::package::{Package, PackageInfo, PackageResult};
+use crate::report::ListReport;
+use crate::status::FileStatus;
 use crate::tool::Tool;
+use crate::transaction::Transaction;
 use crate::utils::{
-    create_dirs, existence, export_bin_dir, iter_includes, CYAN, GREEN, NC, YELLOW,
+    existence, expand_path, iter_includes, make_absolute, prompt, InstallDirs, BLUE, CYAN, GREEN,
+    NC, YELLOW,
 };
 use anyhow::{anyhow, Result};
+use clap::ValueEnum;
 use serde::Deserialize;
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
@@ -23,15 +31,66 @@ pub struct Config {
     pub groups: Option<HashMap<String, Group>>,
     pub default_groups: Option<Vec<String>>,
     pub default_tools: Option<Vec<String>>,
+    pub paths: Option<Paths>,
+    /// What is installed when the action starts, loaded once
+    #[serde(skip)]
+    pub manifest: Manifest,
+    /// Where packages are installed, resolved once from the configuration and the arguments
+    #[serde(skip)]
+    pub dirs: InstallDirs,
+}
+
+/// Directories of a system wide installation, (bin, config, data)
+const SYSTEM_DIRS: (&str, &str, &str) = ("/usr/local/bin", "/etc/xdg", "/usr/local/lib");
+/// Where a system wide installation keeps its manifest, backups and env scripts, apart
+/// from the libraries
+const SYSTEM_STATE_DIR: &str = "/var/lib/editor";
+/// Scripts read by the login shells of every user
+const SYSTEM_PROFILE_DIR: &str = "/etc/profile.d";
+
+/// `[paths]` section, where packages are installed instead of the platform defaults
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct Paths {
+    /// Same as bin, config and data set to <prefix>/bin, <prefix>/etc and <prefix>/share
+    pub prefix: Option<PathBuf>,
+    pub bin: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    pub data: Option<PathBuf>,
 }
 
 impl Config {
     pub fn from_file<P: AsRef<Path>>(path: P, args: &mut Args) -> Result<Config> {
         // Check if config exists
         if path.as_ref().exists() {
-            let config: Config = toml::from_str(&std::fs::read_to_string(path)?)?;
+            let mut config: Config = toml::from_str(&std::fs::read_to_string(&path)?)?;
+            // relative paths are relative to the configuration file
+            let base = make_absolute(&path)?
+                .parent()
+                .map_or(PathBuf::from("/"), |p| p.to_path_buf());
+            config.resolve_paths(&base)?;
+            config.check_env_names()?;
+            config.dirs = config.get_install_dirs(args)?;
+            config.dirs.root = args.root.as_ref().map(make_absolute).transpose()?;
+            let modifying = matches!(
+                args.action,
+                Action::Install
+                    | Action::Remove
+                    | Action::Update
+                    | Action::Restore
+                    | Action::Autoremove
+            );
+            if args.system && modifying && !args.dry_run {
+                config.dirs.check_writable()?;
+            }
+            config.manifest = Manifest::load(&config.dirs)?;
             match args.action {
-                Action::List => Ok(config),
+                Action::List
+                | Action::Restore
+                | Action::Status
+                | Action::Autoremove
+                | Action::Tree
+                | Action::Why
+                | Action::Graph => Ok(config),
                 _ => match config.validate(args) {
                     Ok(_) => {
                         let (mut missing_tools, mut missing_groups) = (Vec::new(), Vec::new());
@@ -79,18 +138,8 @@ impl Config {
                             return Err(anyhow!(res));
                         }
 
-                        // add defautl tools to args to be installed
-                        if let Some(d_tools) = config.default_tools.as_ref() {
-                            args.tools
-                                .get_or_insert(d_tools.to_owned())
-                                .extend_from_slice(d_tools);
-                        }
-                        // add default groups to args to be installed
-                        if let Some(d_groups) = config.default_groups.as_ref() {
-                            args.groups
-                                .get_or_insert(d_groups.to_owned())
-                                .extend_from_slice(d_groups);
-                        }
+                        // add default tools and groups to args to be installed
+                        *args = config.get_selection(args);
                         Ok(config)
                     }
                     Err(e) => Err(e),
@@ -101,27 +150,171 @@ impl Config {
         }
     }
 
-    pub fn install(&self, args: &Args) -> Result<()> {
-        // Create config/bin/data dirs and export bin to path
-        create_dirs()?;
-        let added = export_bin_dir()?;
-        let mut installed: HashMap<String, PackageInfo> = HashMap::new();
+    /// The given tools and groups along with the default ones, as installed
+    pub(crate) fn get_selection(&self, args: &Args) -> Args {
+        let mut selection = args.clone();
+        if let Some(d_tools) = self.default_tools.as_ref() {
+            selection
+                .tools
+                .get_or_insert_with(Vec::new)
+                .extend_from_slice(d_tools);
+        }
+        if let Some(d_groups) = self.default_groups.as_ref() {
+            selection
+                .groups
+                .get_or_insert_with(Vec::new)
+                .extend_from_slice(d_groups);
+        }
+        selection
+    }
 
-        // Install editor
-        if !args.except_editor {
-            self.editor.install(args)?;
+    /// Directories from the arguments first, then the system ones and finally the ones
+    /// from the configuration, a directory given explicitly wins over a prefix
+    fn get_install_dirs(&self, args: &Args) -> Result<InstallDirs> {
+        let paths = self.paths.clone().unwrap_or_default();
+        let arg_prefix = args.prefix.as_ref().map(make_absolute).transpose()?;
+        let pick =
+            |arg: &Option<PathBuf>, path: Option<PathBuf>, dir: &str, system: &str| -> Result<_> {
+                Ok(arg
+                    .as_ref()
+                    .map(make_absolute)
+                    .transpose()?
+                    .or(arg_prefix.as_ref().map(|p| p.join(dir)))
+                    .or(args.system.then(|| PathBuf::from(system)))
+                    .or(path)
+                    .or(paths.prefix.as_ref().map(|p| p.join(dir))))
+            };
+        let (bin, config, data) = SYSTEM_DIRS;
+        let mut dirs = InstallDirs::new(
+            pick(&args.bin_dir, paths.bin.clone(), "bin", bin)?,
+            pick(&args.config_dir, paths.config.clone(), "etc", config)?,
+            pick(&args.data_dir, paths.data.clone(), "share", data)?,
+        )?;
+        if args.system {
+            dirs.state = PathBuf::from(SYSTEM_STATE_DIR);
         }
-        // Install required tools
-        if !args.only_editor {
-            let dependencies = self.get_dependencies(args)?;
-            installed = HashMap::with_capacity(dependencies.satisfied_tools.len());
-            dependencies
-                .list_tools_for_action("\nThe following tools will be installed : ", "\n\n")?;
-            for (tool_key, tool) in &dependencies.satisfied_tools {
-                installed.insert(tool_key.to_string(), tool.install(args)?);
+        Ok(dirs)
+    }
+
+    /// Names of the env variables are written as is in scripts that get sourced, only
+    /// allow what every shell accepts
+    fn check_env_names(&self) -> Result<()> {
+        for (tool_key, tool) in self.tools.iter().flatten() {
+            for name in tool.env.iter().flat_map(|env| env.keys()) {
+                let mut chars = name.chars();
+                let valid = chars
+                    .next()
+                    .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                    && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if !valid {
+                    return Err(anyhow!(
+                        "'{name}' in the env of '{tool_key}' is not a valid variable name"
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_paths(&mut self, base: &Path) -> Result<()> {
+        if let Some(paths) = self.paths.as_mut() {
+            for path in [
+                &mut paths.prefix,
+                &mut paths.bin,
+                &mut paths.config,
+                &mut paths.data,
+            ]
+            .into_iter()
+            .flatten()
+            {
+                *path = expand_path(&path, base)?;
+            }
+        }
+        let editor = &mut self.editor;
+        editor.bin = expand_path(&editor.bin, base)?;
+        editor.config = expand_path(&editor.config, base)?;
+        if let Some(lib) = editor.lib.as_mut() {
+            *lib = expand_path(&lib, base)?;
+        }
+        for tool in self.tools.iter_mut().flat_map(|tools| tools.values_mut()) {
+            tool.bin = expand_path(&tool.bin, base)?;
+            if let Some(config) = tool.config.as_mut() {
+                *config = expand_path(&config, base)?;
             }
+            if let Some(lib) = tool.lib.as_mut() {
+                *lib = expand_path(&lib, base)?;
+            }
+            if let Some(destination) = tool.destination.as_mut() {
+                for path in [
+                    &mut destination.bin,
+                    &mut destination.config,
+                    &mut destination.lib,
+                ]
+                .into_iter()
+                .flatten()
+                {
+                    *path = expand_path(&path, base)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn install(&self, args: &Args) -> Result<()> {
+        // resolved once the defaults are in args, what is checked is what gets installed
+        let dependencies = if args.only_editor {
+            None
+        } else {
+            let dependencies = self.get_dependencies(args)?;
+            dependencies.check_conflicts()?;
+            Some(dependencies)
+        };
+        self.check_collisions(args)?;
+        // Create config/bin/data dirs
+        if !args.dry_run {
+            self.dirs.create()?;
         }
 
+        let (installed, manifest) = Transaction::run(&self.dirs, |tx| {
+            let mut installed: Vec<(String, PackageInfo)> = Vec::new();
+            let mut manifest = self.manifest.clone();
+
+            // Install editor
+            if !args.except_editor {
+                tx.set_key(EDITOR_KEY);
+                let info = self.editor.install(args, tx)?;
+                manifest
+                    .editor
+                    .record(&self.editor, &info, args, &self.dirs)?;
+            }
+            // Install required tools
+            if let Some(dependencies) = dependencies.as_ref() {
+                installed = Vec::with_capacity(dependencies.order.len());
+                dependencies
+                    .list_tools_for_action("\nThe following tools will be installed : ", "\n\n")?;
+                dependencies.print_optional_warnings()?;
+                for (tool_key, tool) in dependencies.ordered_tools() {
+                    tx.set_key(tool_key);
+                    let info = tool.install(args, tx)?;
+                    let record = manifest.tools.entry(tool_key.to_string()).or_default();
+                    // asking for a tool pulled in as a dependency keeps it
+                    let requested = dependencies.requested.contains(tool_key);
+                    record.auto = if record.is_empty() {
+                        !requested
+                    } else {
+                        record.auto && !requested
+                    };
+                    record.record(tool, &info, args, &self.dirs)?;
+                    installed.push((tool_key.to_string(), info));
+                }
+            }
+            manifest.save(args, &self.dirs)?;
+            Ok((installed, manifest))
+        })?;
+
+        // Generate the env scripts once everything is installed
+        let added = self.update_env(args, &manifest)?;
+
         let installed_str = if installed.len() == 0 {
             String::new()
         } else {
@@ -154,33 +347,141 @@ impl Config {
                 .join(", ")
         };
 
-        if added {
+        if args.dry_run {
+            println!(
+                "{BLUE}DRY RUN{NC}: nothing has been modified (tools to install : {installed_str})"
+            );
+        } else if added {
             println!("{GREEN}SUCCESS{NC}: Refresh your terminal for the changes to take effect (tools installed : {installed_str})");
         } else {
             println!("{GREEN}SUCCESS{NC} Your tools ({installed_str}) are righly installed in your system");
+            let shell = args.shell.unwrap_or_else(Shell::detect);
+            if args.system {
+                println!(
+                    "Load them for every user by linking {} in {SYSTEM_PROFILE_DIR}",
+                    Shell::Posix.get_env_path(&self.dirs).display()
+                );
+            } else if !self.dirs.is_staged() && !shell.has_block()? {
+                println!(
+                    "Load them with '{}', from {} for instance (or use --edit-rc)",
+                    shell.get_source_line(&self.dirs),
+                    shell.get_config_path()?.display()
+                );
+            }
         }
         Ok(())
     }
 
-    pub fn remove(&self, args: &Args) -> Result<()> {
-        // Remove editor
-        if !args.except_editor {
-            self.editor.remove(args)?;
+    /// Regenerate the env scripts from what is installed, true if the shell configuration
+    /// has just been made to source them. The shell of a staged installation is not the
+    /// one of this machine and a system one does not belong to a single user
+    fn update_env(&self, args: &Args, manifest: &Manifest) -> Result<bool> {
+        let shell = args.shell.unwrap_or_else(Shell::detect);
+        let edit_rc = args.edit_rc && !self.dirs.is_staged() && !args.system;
+        if args.dry_run {
+            println!(
+                "{BLUE}PLAN{NC}: update the env scripts in {}",
+                self.dirs.staged(Shell::get_env_dir(&self.dirs)).display()
+            );
+            if edit_rc && !shell.has_block()? {
+                println!(
+                    "{BLUE}PLAN{NC}: add '{}' to {}",
+                    shell.get_source_line(&self.dirs),
+                    shell.get_config_path()?.display()
+                );
+            }
+            return Ok(false);
         }
-        // Remove required tools
-        if !args.only_editor {
-            let dependencies = self.get_dependencies(args)?;
-            dependencies
-                .list_tools_for_action("\nThe following tools will be removed :", "\n\n")?;
-            let mut removed = HashMap::with_capacity(dependencies.satisfied_tools.len());
-            let mut res: PackageInfo;
-            // this remove tools of groups also
-            for (tool_key, tool) in &dependencies.satisfied_tools {
-                res = tool.remove(args)?;
-                removed.insert(tool_key.to_owned(), res);
+        if manifest.is_empty() {
+            Shell::remove_env_scripts(&self.dirs)?;
+            // even without --edit-rc, a block would source a script that is gone, whatever
+            // the shell it has been added for
+            if !self.dirs.is_staged() && !args.system {
+                for shell in Shell::value_variants() {
+                    if shell.remove_block()? {
+                        println!(
+                            "{YELLOW}UPDATED{NC}: {} no longer sources the env script",
+                            shell.get_config_path()?.display()
+                        );
+                    }
+                }
+            }
+            return Ok(false);
+        }
+        Shell::write_env_scripts(&self.dirs, &self.get_env_vars(manifest))?;
+        if edit_rc {
+            shell.add_block(&self.dirs)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Variables of the installed tools, in order of tool keys
+    fn get_env_vars(&self, manifest: &Manifest) -> Vec<(String, String)> {
+        let mut keys: Vec<&String> = manifest
+            .tools
+            .iter()
+            .filter(|(_, record)| !record.is_empty())
+            .map(|(key, _)| key)
+            .collect();
+        keys.sort();
+        let mut vars: BTreeMap<String, String> = BTreeMap::new();
+        for key in keys {
+            let tool = self.tools.as_ref().and_then(|tools| tools.get(key));
+            for (name, value) in tool
+                .and_then(|tool| tool.env.as_ref())
+                .into_iter()
+                .flatten()
+            {
+                if vars
+                    .insert(name.to_owned(), value.to_owned())
+                    .is_some_and(|old| old != *value)
+                {
+                    println!(
+                        "{YELLOW}WARNING{NC}: '{name}' is set by several tools, the value of '{key}' is used"
+                    );
+                }
+            }
+        }
+        vars.into_iter().collect()
+    }
+
+    pub fn remove(&self, args: &Args) -> Result<()> {
+        let tools = self.get_removed_tools(args)?;
+        let (removed, manifest) = Transaction::run(&self.dirs, |tx| {
+            let mut manifest = self.manifest.clone();
+            // Remove editor
+            if !args.except_editor {
+                Config::remove_package(&self.editor, &mut manifest.editor, args, tx)?;
+            }
+            // Remove required tools
+            let mut removed = Vec::with_capacity(tools.len());
+            if !tools.is_empty() {
+                let keys: Vec<&str> = tools.iter().map(|(key, _)| key.as_str()).collect();
+                println!(
+                    "\nThe following tools will be removed : {}\n",
+                    keys.join(", ")
+                );
+            }
+            // this remove tools of groups also, dependents first
+            for (tool_key, tool) in tools.iter().rev() {
+                let record = manifest.tools.entry(tool_key.to_string()).or_default();
+                let res: PackageInfo = Config::remove_package(*tool, record, args, tx)?;
+                removed.push((tool_key.to_owned(), res));
             }
+            manifest.save(args, &self.dirs)?;
+            Ok((removed, manifest))
+        })?;
+        self.update_env(args, &manifest)?;
+
+        if !args.only_editor {
             println!(
-                "The following tools have been removed : {}",
+                "The following tools {} : {}",
+                if args.dry_run {
+                    "would be removed"
+                } else {
+                    "have been removed"
+                },
                 removed
                     .iter()
                     .map(|(key, (lib, config))| {
@@ -200,7 +501,510 @@ impl Config {
             );
         }
 
-        println!("{GREEN}SUCCESS{NC}");
+        if args.dry_run {
+            println!("{BLUE}DRY RUN{NC}: nothing has been modified");
+        } else {
+            println!("{GREEN}SUCCESS{NC}");
+        }
+        Ok(())
+    }
+
+    /// Fail before touching anything when selected packages would install to the same place
+    fn check_collisions(&self, args: &Args) -> Result<()> {
+        let mut destinations: Vec<(PathBuf, String)> = Vec::new();
+        if !args.except_editor {
+            for target in self.editor.get_install_targets(args, &self.dirs)? {
+                let owner = format!("editor {GREEN}{}{NC} ({})", self.editor.name, target.field);
+                destinations.push((target.destination, owner));
+            }
+        }
+        if !args.only_editor {
+            for (tool_key, tool) in self.get_dependencies(args)?.ordered_tools() {
+                for target in tool.get_install_targets(args, &self.dirs)? {
+                    let owner = format!("tool {GREEN}{tool_key}{NC} ({})", target.field);
+                    destinations.push((target.destination, owner));
+                }
+            }
+        }
+
+        let collisions = Config::find_collisions(&destinations);
+        if collisions.is_empty() {
+            return Ok(());
+        }
+
+        println!("\nThe following destinations are used by several packages :\n");
+        for (path, owners) in collisions {
+            let owners: Vec<&str> = owners.into_iter().map(|o| o.as_str()).collect();
+            println!(" - {} : {}", path.display(), owners.join(", "));
+        }
+        std::io::stdout().flush()?;
+        Err(anyhow!(
+            "Packages would overwrite each other, give them different names or configuration file names"
+        ))
+    }
+
+    /// Destinations shared by several owners, a destination inside another one is
+    /// overwritten as well
+    fn find_collisions(destinations: &[(PathBuf, String)]) -> Vec<(&PathBuf, Vec<&String>)> {
+        let mut collisions: Vec<(&PathBuf, Vec<&String>)> = Vec::new();
+        for (index, (path, owner)) in destinations.iter().enumerate() {
+            for (other_path, other_owner) in &destinations[index + 1..] {
+                if !path.starts_with(other_path) && !other_path.starts_with(path) {
+                    continue;
+                }
+                let shortest = if path.starts_with(other_path) {
+                    other_path
+                } else {
+                    path
+                };
+                match collisions.iter_mut().find(|(p, _)| *p == shortest) {
+                    Some((_, owners)) => {
+                        for o in [owner, other_owner] {
+                            if !owners.contains(&o) {
+                                owners.push(o);
+                            }
+                        }
+                    }
+                    None => collisions.push((shortest, vec![owner, other_owner])),
+                }
+            }
+        }
+        collisions
+    }
+
+    /// Remove tools installed as dependencies that no installed tool requires anymore
+    pub fn autoremove(&self, args: &Args) -> Result<()> {
+        let mut manifest = self.manifest.clone();
+        let tools = self.tools.as_ref();
+        let installed = |auto: bool| -> Vec<String> {
+            let mut keys: Vec<String> = manifest
+                .tools
+                .iter()
+                .filter(|(key, record)| {
+                    !record.is_empty()
+                        && record.auto == auto
+                        && tools.is_some_and(|tools| tools.contains_key(*key))
+                })
+                .map(|(key, _)| key.to_owned())
+                .collect();
+            keys.sort();
+            keys
+        };
+        let (manual, auto) = (installed(false), installed(true));
+
+        // everything reachable from the tools asked for by the user is kept
+        let mut kept_args = args.clone();
+        (kept_args.tools, kept_args.groups) = (Some(manual.clone()), None);
+        let kept: Vec<String> = if manual.is_empty() {
+            Vec::new()
+        } else {
+            self.get_dependencies(&kept_args)?
+                .checked_tool_keys
+                .iter()
+                .map(|key| key.to_string())
+                .collect()
+        };
+        let orphans: Vec<String> = auto.into_iter().filter(|key| !kept.contains(key)).collect();
+        if orphans.is_empty() {
+            println!("{GREEN}SUCCESS{NC}: No tool to remove");
+            return Ok(());
+        }
+
+        let mut orphan_args = args.clone();
+        (orphan_args.tools, orphan_args.groups) = (Some(orphans.clone()), None);
+        let dependencies = self.get_dependencies(&orphan_args)?;
+        // orphans depending on other orphans are removed first
+        let mut ordered: Vec<(&String, &Tool)> = dependencies
+            .ordered_tools()
+            .into_iter()
+            .rev()
+            .filter(|(key, _)| orphans.contains(key))
+            .collect();
+        for key in &orphans {
+            if !ordered.iter().any(|(k, _)| *k == key) {
+                ordered.push((key, &tools.unwrap()[key]));
+            }
+        }
+        println!(
+            "\nThe following tools are no longer required : {}\n",
+            ordered
+                .iter()
+                .map(|(key, _)| key.as_str())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        );
+
+        Transaction::run(&self.dirs, |tx| {
+            for (tool_key, tool) in &ordered {
+                let record = manifest.tools.entry(tool_key.to_string()).or_default();
+                Config::remove_package(*tool, record, args, tx)?;
+            }
+            manifest.save(args, &self.dirs)
+        })?;
+        self.update_env(args, &manifest)?;
+
+        if args.dry_run {
+            println!("{BLUE}DRY RUN{NC}: nothing has been modified");
+        } else {
+            println!("{GREEN}SUCCESS{NC}");
+        }
+        Ok(())
+    }
+
+    /// Tools to remove in installation order, making sure no installed tool loses one of
+    /// its dependencies, with `--cascade` these dependents are removed too. Only the tools
+    /// asked for are removed, their dependencies are left to autoremove
+    fn get_removed_tools(&self, args: &Args) -> Result<Vec<(&String, &Tool)>> {
+        if args.only_editor {
+            return Ok(Vec::new());
+        }
+        let manifest = &self.manifest;
+        let dependencies = self.get_dependencies(args)?;
+        let order: Vec<String> = dependencies
+            .order
+            .into_iter()
+            .filter(|key| dependencies.requested.contains(key))
+            .collect();
+        let mut removed = order.clone();
+        let mut dependents = Vec::new();
+        loop {
+            let found = self.get_installed_dependents(&removed, manifest);
+            if found.is_empty() {
+                break;
+            }
+            removed.extend(found.iter().map(|(key, _)| key.to_owned()));
+            dependents.extend(found);
+            // dependents of dependents are only needed when cascading
+            if !args.cascade {
+                break;
+            }
+        }
+        if !dependents.is_empty() {
+            println!(
+                "\n{YELLOW}WARNING{NC}: The following installed tools depend on tools to remove :"
+            );
+            for (key, required) in &dependents {
+                println!(" - {GREEN}{key}{NC} requires {}", required.join(", "));
+            }
+        }
+        if !args.cascade {
+            if dependents.is_empty()
+                || args.confirm(
+                    &format!(
+                        "{YELLOW}WARNING{NC}: Remove them anyway and break these tools (y/N): "
+                    ),
+                    false,
+                )?
+            {
+                return Ok(self.get_tools(&order));
+            }
+            return Err(anyhow!(
+                "Tools are still required by installed tools, use --cascade to remove them too"
+            ));
+        }
+
+        // dependents are installed after the tools they depend on, the resolver only gives
+        // the order, the other dependencies of the dependents are kept
+        let mut order_args = args.clone();
+        order_args.tools = Some(removed.clone());
+        order_args.groups = None;
+        let mut order: Vec<String> = self
+            .get_dependencies(&order_args)?
+            .order
+            .into_iter()
+            .filter(|key| removed.contains(key))
+            .collect();
+        for key in removed {
+            if !order.contains(&key) {
+                order.push(key);
+            }
+        }
+        // checked again on what is actually removed
+        let remaining = self.get_installed_dependents(&order, manifest);
+        if !remaining.is_empty() {
+            let keys: Vec<&str> = remaining.iter().map(|(key, _)| key.as_str()).collect();
+            return Err(anyhow!(
+                "Installed tools would lose their dependencies: {}",
+                keys.join(", ")
+            ));
+        }
+        Ok(self.get_tools(&order))
+    }
+
+    /// Tools of the configuration having one of `keys`, in the same order
+    fn get_tools(&self, keys: &[String]) -> Vec<(&String, &Tool)> {
+        keys.iter()
+            .filter_map(|key| self.tools.as_ref()?.get_key_value(key))
+            .collect()
+    }
+
+    /// Installed tools, outside of `removed`, with the tools of `removed` they depend on
+    fn get_installed_dependents(
+        &self,
+        removed: &[String],
+        manifest: &Manifest,
+    ) -> Vec<(String, Vec<String>)> {
+        let mut dependents: Vec<(String, Vec<String>)> = self
+            .tools
+            .iter()
+            .flatten()
+            .filter(|(key, _)| !removed.contains(key) && manifest.is_installed(key))
+            .filter_map(|(key, tool)| {
+                let mut required: Vec<String> = tool
+                    .dependencies
+                    .iter()
+                    .flatten()
+                    .filter(|dep| removed.contains(dep))
+                    .cloned()
+                    .collect();
+                // a capability matters once none of its providers would stay installed
+                for dep in tool.dependencies.iter().flatten() {
+                    let providers: Vec<&String> = self
+                        .get_providers(dep)
+                        .into_iter()
+                        .filter(|provider| manifest.is_installed(provider))
+                        .collect();
+                    if !providers.is_empty()
+                        && providers.iter().all(|provider| removed.contains(provider))
+                    {
+                        required.extend(providers.into_iter().cloned());
+                    }
+                }
+                // alternatives matter once none of them would stay installed
+                let alternatives: Vec<&String> = tool
+                    .one_of
+                    .iter()
+                    .flatten()
+                    .filter(|dep| manifest.is_installed(dep))
+                    .collect();
+                if !alternatives.is_empty() && alternatives.iter().all(|dep| removed.contains(dep))
+                {
+                    required.extend(alternatives.into_iter().cloned());
+                }
+                (!required.is_empty()).then(|| (key.to_owned(), required))
+            })
+            .collect();
+        dependents.sort();
+        dependents
+    }
+
+    // Only act on files installed by this tool, unmanaged ones are kept unless forced
+    fn remove_package<P: Package>(
+        package: &P,
+        record: &mut ManifestRecord,
+        args: &Args,
+        tx: &mut Transaction,
+    ) -> Result<PackageInfo> {
+        if !record.is_empty() {
+            package.remove_recorded(record, args, tx)
+        } else if args.force {
+            package.remove(args, tx)
+        } else {
+            // installed before the manifest existed or by hand, kept unless forced
+            let present: Vec<PathBuf> = package
+                .get_install_targets(args, tx.dirs)?
+                .into_iter()
+                .map(|target| target.destination)
+                .filter(|path| {
+                    let path = tx.dirs.staged(path);
+                    path.exists() || path.is_symlink()
+                })
+                .collect();
+            if present.is_empty() {
+                println!("{YELLOW}WARNING{NC}: '{}' is not installed", package.name());
+            } else {
+                println!(
+                    "{YELLOW}WARNING{NC}: '{}' has not been installed by this tool, these files are kept, use --force to remove them anyway :",
+                    package.name()
+                );
+                for path in present {
+                    println!("\t{}", path.display());
+                }
+            }
+            Ok((PackageResult::Ignored, PackageResult::Ignored))
+        }
+    }
+
+    pub fn update(&self, args: &Args) -> Result<()> {
+        let (updated, manifest) = Transaction::run(&self.dirs, |tx| {
+            let mut updated: Vec<(String, PackageInfo)> = Vec::new();
+            let mut manifest = self.manifest.clone();
+
+            // Update editor
+            if !args.except_editor && !manifest.editor.is_empty() {
+                tx.set_key(EDITOR_KEY);
+                let info = self.editor.update(args, &manifest.editor, tx)?;
+                manifest
+                    .editor
+                    .record(&self.editor, &info, args, &self.dirs)?;
+                updated.push((self.editor.name.to_owned(), info));
+            }
+            // Update required tools that are installed
+            if !args.only_editor {
+                let dependencies = self.get_dependencies(args)?;
+                dependencies
+                    .list_tools_for_action("\nThe following tools will be checked : ", "\n\n")?;
+                for (tool_key, tool) in dependencies.ordered_tools() {
+                    if let Some(record) = manifest.tools.get_mut(tool_key.as_str()) {
+                        tx.set_key(tool_key);
+                        let info = tool.update(args, record, tx)?;
+                        record.record(tool, &info, args, &self.dirs)?;
+                        updated.push((tool_key.to_string(), info));
+                    }
+                }
+            }
+            manifest.save(args, &self.dirs)?;
+            Ok((updated, manifest))
+        })?;
+        // variables may have changed in the configuration
+        self.update_env(args, &manifest)?;
+
+        let (mut changed, mut up_to_date) = (Vec::new(), Vec::new());
+        for (key, (bin, config)) in &updated {
+            let data = [("bin", bin), ("config", config)]
+                .iter()
+                .filter_map(|(label, res)| match res {
+                    PackageResult::Installed => Some(format!("{label}: installed")),
+                    PackageResult::Linked => Some(format!("{label}: linked")),
+                    _ => None,
+                })
+                .collect::<Vec<String>>();
+            if !data.is_empty() {
+                changed.push(format!("{GREEN}{key}{NC} ({})", data.join(", ")));
+            } else if matches!(bin, PackageResult::UpToDate)
+                || matches!(config, PackageResult::UpToDate)
+            {
+                up_to_date.push(format!("{GREEN}{key}{NC}"));
+            }
+        }
+
+        if !up_to_date.is_empty() {
+            println!("Already up to date : {}", up_to_date.join(", "));
+        }
+        if changed.is_empty() {
+            println!("{GREEN}SUCCESS{NC}: Nothing to update");
+        } else {
+            println!("{GREEN}SUCCESS{NC}: Updated {}", changed.join(", "));
+        }
+        Ok(())
+    }
+
+    pub fn status(&self, args: &Args) -> Result<()> {
+        let manifest = &self.manifest;
+        let mut drifted = 0;
+        let mut count = |files: &[(String, PathBuf, FileStatus)]| {
+            if files.iter().any(|(_, _, s)| *s != FileStatus::UpToDate) {
+                drifted += 1;
+            }
+        };
+
+        if !args.except_editor {
+            let files =
+                FileStatus::of_package(&self.editor, Some(&manifest.editor), args, &self.dirs)?;
+            FileStatus::print(&format!("Editor: {GREEN}{}{NC}", self.editor.name), &files)?;
+            count(&files);
+        }
+        if !args.only_editor {
+            if let Some(tools) = self.tools.as_ref() {
+                let mut keys: Vec<&String> = match args.tools.as_ref() {
+                    Some(keys) => keys.iter().filter(|k| tools.contains_key(*k)).collect(),
+                    None => tools.keys().collect(),
+                };
+                keys.sort();
+                keys.dedup();
+                for tool_key in keys {
+                    let tool = &tools[tool_key];
+                    let files = FileStatus::of_package(
+                        tool,
+                        manifest.tools.get(tool_key.as_str()),
+                        args,
+                        &self.dirs,
+                    )?;
+                    FileStatus::print(
+                        &format!(
+                            "Tool: {GREEN}{tool_key}{NC} (lsp: {GREEN}{}{NC})",
+                            tool.name
+                        ),
+                        &files,
+                    )?;
+                    count(&files);
+                }
+            }
+        }
+
+        if drifted == 0 {
+            println!("\n{GREEN}SUCCESS{NC}: Everything is up to date");
+        } else {
+            println!("\n{YELLOW}WARNING{NC}: {drifted} package(s) differ from the configuration");
+        }
+        Ok(())
+    }
+
+    pub fn restore(&self, args: &Args) -> Result<()> {
+        let mut keys = Vec::new();
+        if !args.except_editor {
+            keys.push(EDITOR_KEY.to_string());
+        }
+        if !args.only_editor {
+            match args.tools.as_ref() {
+                Some(tools) => keys.extend(tools.iter().cloned()),
+                None => keys.extend(
+                    Backup::keys(&self.dirs)?
+                        .into_iter()
+                        .filter(|k| k != EDITOR_KEY),
+                ),
+            }
+        }
+
+        let restored = Transaction::run(&self.dirs, |tx| {
+            let mut restored = Vec::new();
+            let mut manifest = self.manifest.clone();
+            for key in &keys {
+                let mut backups = Backup::list(&self.dirs, key)?;
+                if backups.is_empty() {
+                    continue;
+                }
+                println!("\nBackups of {GREEN}{key}{NC} :");
+                for (index, backup) in backups.iter().enumerate() {
+                    println!("\t{index}) {}", backup.describe());
+                }
+                let choice = if args.yes {
+                    // latest backup
+                    (backups.len() - 1).to_string()
+                } else if args.is_interactive() {
+                    prompt("Which one do you want to restore (empty to skip): ")?
+                } else {
+                    String::new()
+                };
+                if choice.trim().is_empty() {
+                    continue;
+                }
+                match choice.trim().parse::<usize>() {
+                    Ok(index) if index < backups.len() => {
+                        let backup = backups.remove(index);
+                        backup.restore(key, args, tx)?;
+                        // what is put back belongs to the user, not to a package anymore
+                        for entry in &backup.files {
+                            manifest.forget(&entry.original, tx.dirs);
+                        }
+                        restored.push(backup);
+                    }
+                    _ => return Err(anyhow!("'{}' is not a valid backup", choice.trim())),
+                }
+            }
+            manifest.save(args, &self.dirs)?;
+            Ok(restored)
+        })?;
+
+        if restored.is_empty() {
+            println!("{GREEN}SUCCESS{NC}: Nothing to restore");
+        } else if !args.dry_run {
+            // restored files are back in place, the backups are not needed anymore
+            for backup in restored {
+                backup.delete()?;
+            }
+            println!("{GREEN}SUCCESS{NC}");
+        }
         Ok(())
     }
 
@@ -263,9 +1067,9 @@ impl Config {
         Ok(())
     }
 
-    fn get_dependencies<'l>(&'l self, args: &'l Args) -> Result<Dependencies> {
+    pub(crate) fn get_dependencies<'l>(&'l self, args: &'l Args) -> Result<Dependencies<'l>> {
         // Create dependencies
-        let mut dependencies = Dependencies::default();
+        let mut dependencies = Dependencies::new(&self.manifest);
         if let Some(tools) = self.tools.as_ref() {
             let available_tool_keys: Vec<&String> = tools.keys().collect();
             let mut required_tools: Vec<(&String, &Tool)> = vec![];
@@ -282,17 +1086,18 @@ impl Config {
                 }
             }
             // For each group check if it exists
+            let mut required_capabilities: Vec<&String> = vec![];
             if let Some(groups) = self.groups.as_ref() {
                 if let Some(groups_args) = args.groups.as_ref() {
                     for group_key in groups_args {
-                        if let Some(group) = groups.get(group_key) {
-                            // For each dependencies check if it's available
-                            for tool_key in &group.dependencies {
+                        if groups.contains_key(group_key) {
+                            // For each dependencies, included groups expanded, check if it's available
+                            for tool_key in Group::expand(group_key, groups, Some(tools)).tools {
                                 match tools.get(tool_key) {
                                     Some(tool) => {
                                         required_tools.push((tool_key, tool));
                                     }
-                                    None => (),
+                                    None => required_capabilities.push(tool_key),
                                 }
                             }
                         }
@@ -300,9 +1105,15 @@ impl Config {
                 }
             }
             // If nothing is required, install everything
-            if required_tools.is_empty() {
+            if required_tools.is_empty() && required_capabilities.is_empty() {
                 required_tools = tools.iter().collect();
+                // sorted for the order to be the same between runs
+                required_tools.sort_by_key(|(key, _)| *key);
             }
+            dependencies.requested = required_tools
+                .iter()
+                .map(|(key, _)| key.to_string())
+                .collect();
             for (tool_key, tool) in &required_tools {
                 // check paths for each Tool
                 let invalid_paths = tool.get_invalid_paths()?;
@@ -326,18 +1137,56 @@ impl Config {
             // Create dependencies recursively
             for (tool_key, tool) in &required_tools {
                 // call the recursivity for invalid dependencies
-                self.get_dependencies_rec(&available_tool_keys, tool, tool_key, &mut dependencies);
+                self.get_dependencies_rec(&available_tool_keys, tool, tool_key, &mut dependencies)?;
+            }
+            // the provider chosen for a capability of a group is asked for as well
+            for capability in required_capabilities {
+                if self
+                    .resolve_capability(&available_tool_keys, capability, &mut dependencies)?
+                    .is_ok()
+                {
+                    let provider = dependencies.providers[capability].to_owned();
+                    if !dependencies.requested.contains(&provider) {
+                        dependencies.requested.push(provider);
+                    }
+                }
             }
+            // selected tools in conflict are only refused when installing
+            let mut selected: Vec<(&String, &&Tool)> =
+                dependencies.satisfied_tools.iter().collect();
+            selected.sort_by_key(|(key, _)| *key);
+            let mut conflicts = Vec::new();
+            for (tool_key, tool) in selected {
+                for other in tool.conflicts.iter().flatten() {
+                    if dependencies.satisfied_tools.contains_key(other)
+                        && !conflicts.contains(&(other.to_owned(), tool_key.to_owned()))
+                    {
+                        conflicts.push((tool_key.to_owned(), other.to_owned()));
+                    }
+                }
+            }
+            dependencies.conflicts = conflicts;
 
             if let Some(groups) = self.groups.as_ref() {
                 if let Some(groups_args) = args.groups.as_ref() {
                     for group_key in groups_args {
                         match groups.get(group_key) {
                             Some(g) => {
-                                for tool in &g.dependencies {
+                                let expanded = Group::expand(group_key, groups, Some(tools));
+                                if expanded.cycle.is_some() {
+                                    dependencies.unsatisfied_groups.insert(
+                                        group_key.to_string(),
+                                        UnSatisfiedGroup {
+                                            group: g,
+                                            unsatisfied_tools: Vec::new(),
+                                            cycle: expanded.cycle,
+                                        },
+                                    );
+                                }
+                                for tool in expanded.tools {
                                     // tool that group needs is not satisfied
                                     if dependencies.unsatisfied_tools.contains_key(tool)
-                                        || !dependencies.satisfied_tools.contains_key(tool)
+                                        || !dependencies.is_satisfied(tool)
                                     {
                                         dependencies
                                             .unsatisfied_groups
@@ -345,6 +1194,7 @@ impl Config {
                                             .or_insert(UnSatisfiedGroup {
                                                 group: g,
                                                 unsatisfied_tools: Vec::new(),
+                                                cycle: None,
                                             })
                                             .unsatisfied_tools
                                             .push(tool);
@@ -373,88 +1223,247 @@ impl Config {
         current_tool: &'l Tool,
         current_tool_key: &'l String,
         dependencies: &mut Dependencies<'l>,
-    ) {
+    ) -> Result<()> {
         if available_tool_keys.contains(&current_tool_key)
             && !dependencies.satisfied_tools.contains_key(current_tool_key)
         {
             // avoid endless recursivity
             dependencies.checked_tool_keys.push(current_tool_key);
-            let mut valid = true;
-            if let Some(required) = current_tool.dependencies.as_ref() {
-                for tool_key in required {
-                    if let Some(required_tool_key) = self.tools.as_ref().unwrap().get(tool_key) {
-                        if !dependencies.checked_tool_keys.contains(&tool_key) {
-                            self.get_dependencies_rec(
-                                available_tool_keys,
-                                required_tool_key,
-                                tool_key,
-                                dependencies,
-                            );
-                        }
-                        if dependencies.unsatisfied_tools.contains_key(tool_key) {
-                            valid = false;
-                            let required = (
-                                Tool {
-                                    name: tool_key.to_owned(),
-                                    bin: PathBuf::new(),
-                                    config: None,
-                                    lib: None,
-                                    dependencies: None,
-                                },
-                                DependencyErrorType::UnSatisfiedDepencies,
-                            );
-                            dependencies
-                                .unsatisfied_tools
-                                .entry(current_tool_key.to_string())
-                                .or_insert(UnSatisfiedTool {
-                                    tool: current_tool,
-                                    required: Some(HashMap::new()),
-                                    paths: None,
-                                })
-                                .required
-                                .get_or_insert(HashMap::new())
-                                .entry(tool_key)
-                                .or_insert(required);
-                        }
-                    } else {
-                        valid = false;
-                        let required = (
-                            Tool {
-                                name: tool_key.to_owned(),
-                                bin: PathBuf::new(),
-                                config: None,
-                                lib: None,
-                                dependencies: None,
-                            },
-                            DependencyErrorType::NotFound,
-                        );
-                        dependencies
-                            .unsatisfied_tools
-                            .entry(current_tool_key.to_string())
-                            .or_insert(UnSatisfiedTool {
-                                tool: current_tool,
-                                required: Some(HashMap::new()),
-                                paths: None,
-                            })
-                            .required
-                            .get_or_insert(HashMap::new())
-                            .entry(tool_key)
-                            .or_insert(required);
+            dependencies.visiting_tool_keys.push(current_tool_key);
+            // a tool with missing files cannot be satisfied, even as a dependency
+            let invalid_paths = current_tool.get_invalid_paths()?;
+            let mut valid = invalid_paths.is_empty();
+            if !valid {
+                dependencies
+                    .unsatisfied_tools
+                    .entry(current_tool_key.to_string())
+                    .or_insert(UnSatisfiedTool {
+                        tool: current_tool,
+                        required: None,
+                        paths: Some(HashMap::new()),
+                    })
+                    .paths
+                    .get_or_insert(HashMap::new())
+                    .extend(invalid_paths);
+            }
+            let mut edges = Vec::new();
+            for tool_key in current_tool.dependencies.iter().flatten() {
+                edges.push((tool_key, Edge::Required));
+                if let Err(error) =
+                    self.resolve_dependency(available_tool_keys, tool_key, dependencies)?
+                {
+                    valid = false;
+                    dependencies.add_required_error(
+                        current_tool_key,
+                        current_tool,
+                        tool_key,
+                        error,
+                    );
+                }
+            }
+            // unavailable optional dependencies are only warned about when installing
+            for tool_key in current_tool.optional_dependencies.iter().flatten() {
+                edges.push((tool_key, Edge::Optional));
+                self.resolve_if_satisfied(available_tool_keys, tool_key, dependencies)?;
+            }
+            if let Some(alternatives) = current_tool.one_of.as_ref().filter(|a| !a.is_empty()) {
+                let mut chosen = None;
+                for tool_key in self.sort_alternatives(alternatives, dependencies) {
+                    if self.resolve_if_satisfied(available_tool_keys, tool_key, dependencies)? {
+                        chosen = Some(tool_key);
+                        break;
                     }
                 }
+                if let Some(tool_key) = chosen {
+                    edges.push((tool_key, Edge::Alternative));
+                } else {
+                    edges.extend(alternatives.iter().map(|key| (key, Edge::Alternative)));
+                    valid = false;
+                    dependencies.add_required_error(
+                        current_tool_key,
+                        current_tool,
+                        &alternatives[0],
+                        DependencyErrorType::NoneAvailable(alternatives.to_owned()),
+                    );
+                }
             }
+            dependencies.visiting_tool_keys.pop();
+            dependencies
+                .edges
+                .insert(current_tool_key.to_string(), edges);
             if valid {
                 dependencies
                     .satisfied_tools
                     .entry(current_tool_key.to_string())
                     .or_insert(current_tool);
+                // dependencies have been added before, during the recursion
+                dependencies.order.push(current_tool_key.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolve `tool_key` on a copy of `dependencies`, kept only when it is satisfied so
+    /// that an unavailable tool leaves no error behind
+    fn resolve_if_satisfied<'l>(
+        &'l self,
+        available_tool_keys: &[&String],
+        tool_key: &'l String,
+        dependencies: &mut Dependencies<'l>,
+    ) -> Result<bool> {
+        let mut scratch = dependencies.clone();
+        let satisfied = self
+            .resolve_dependency(available_tool_keys, tool_key, &mut scratch)?
+            .is_ok();
+        if satisfied {
+            *dependencies = scratch;
+        }
+        Ok(satisfied)
+    }
+
+    /// Resolve the dependency `tool_key`, the inner error tells why it is not satisfied
+    fn resolve_dependency<'l>(
+        &'l self,
+        available_tool_keys: &[&String],
+        tool_key: &'l String,
+        dependencies: &mut Dependencies<'l>,
+    ) -> Result<std::result::Result<(), DependencyErrorType>> {
+        let Some((tool_key, tool)) = self.tools.as_ref().unwrap().get_key_value(tool_key) else {
+            return self.resolve_capability(available_tool_keys, tool_key, dependencies);
+        };
+        if let Some(position) = dependencies
+            .visiting_tool_keys
+            .iter()
+            .position(|key| *key == tool_key)
+        {
+            // every tool of the cycle depends on the next one of the path
+            let cycle: Vec<&'l String> = dependencies.visiting_tool_keys[position..].to_vec();
+            let path: Vec<String> = cycle
+                .iter()
+                .chain([&tool_key])
+                .map(|key| key.to_string())
+                .collect();
+            for (index, key) in cycle.iter().enumerate() {
+                let next = cycle.get(index + 1).copied().unwrap_or(tool_key);
+                if let Some(tool) = self.tools.as_ref().unwrap().get(*key) {
+                    dependencies.add_required_error(
+                        key,
+                        tool,
+                        next,
+                        DependencyErrorType::Cycle(path.clone()),
+                    );
+                }
             }
+            return Ok(Err(DependencyErrorType::Cycle(path)));
+        }
+        if !dependencies.checked_tool_keys.contains(&tool_key) {
+            self.get_dependencies_rec(available_tool_keys, tool, tool_key, dependencies)?;
+        }
+        if dependencies.satisfied_tools.contains_key(tool_key) {
+            Ok(Ok(()))
+        } else {
+            Ok(Err(DependencyErrorType::UnSatisfiedDepencies))
         }
     }
 
+    /// Pick the first satisfied tool providing `capability`
+    fn resolve_capability<'l>(
+        &'l self,
+        available_tool_keys: &[&String],
+        capability: &'l String,
+        dependencies: &mut Dependencies<'l>,
+    ) -> Result<std::result::Result<(), DependencyErrorType>> {
+        let providers = self.get_providers(capability);
+        if providers.is_empty() {
+            return Ok(Err(DependencyErrorType::NotFound));
+        }
+        for provider in self.sort_alternatives(providers.iter().copied(), dependencies) {
+            if self.resolve_if_satisfied(available_tool_keys, provider, dependencies)? {
+                dependencies
+                    .providers
+                    .insert(capability.to_string(), provider.to_string());
+                return Ok(Ok(()));
+            }
+        }
+        Ok(Err(DependencyErrorType::NoneAvailable(
+            providers.into_iter().cloned().collect(),
+        )))
+    }
+
+    /// Keys of the tools declaring `capability` in their `provides`, sorted
+    pub(crate) fn get_providers(&self, capability: &str) -> Vec<&String> {
+        let mut providers: Vec<&String> = self
+            .tools
+            .iter()
+            .flatten()
+            .filter(|(_, tool)| {
+                tool.provides
+                    .iter()
+                    .flatten()
+                    .any(|provided| provided == capability)
+            })
+            .map(|(key, _)| key)
+            .collect();
+        providers.sort();
+        providers
+    }
+
+    /// Tool satisfying `capability`, the one chosen by the resolver or the preferred one
+    pub(crate) fn get_selected_provider<'l>(
+        &'l self,
+        capability: &String,
+        dependencies: &'l Dependencies,
+    ) -> Option<&'l String> {
+        dependencies.providers.get(capability).or_else(|| {
+            self.sort_alternatives(self.get_providers(capability), dependencies)
+                .into_iter()
+                .find(|key| dependencies.satisfied_tools.contains_key(*key))
+        })
+    }
+
+    /// Capabilities with the keys of the tools providing them
+    pub(crate) fn get_capabilities(&self) -> BTreeMap<&String, Vec<&String>> {
+        let mut capabilities: BTreeMap<&String, Vec<&String>> = BTreeMap::new();
+        for (key, tool) in self.tools.iter().flatten() {
+            for capability in tool.provides.iter().flatten() {
+                capabilities.entry(capability).or_default().push(key);
+            }
+        }
+        capabilities
+            .values_mut()
+            .for_each(|providers| providers.sort());
+        capabilities
+    }
+
+    /// Already installed first, then the ones asked for, then the default ones,
+    /// then the given order
+    fn sort_alternatives<'l, I>(
+        &self,
+        alternatives: I,
+        dependencies: &Dependencies,
+    ) -> Vec<&'l String>
+    where
+        I: IntoIterator<Item = &'l String>,
+    {
+        let mut sorted: Vec<&String> = alternatives.into_iter().collect();
+        sorted.sort_by_key(|key| {
+            (
+                !dependencies.manifest.is_installed(key),
+                !dependencies.requested.contains(key),
+                !self.default_tools.as_ref().is_some_and(|d| d.contains(key)),
+            )
+        });
+        sorted
+    }
+
     pub fn list(&self, args: &Args) -> Result<()> {
         let args_cloned = args.clone_with_everything(self);
         let dependencies = self.get_dependencies(&args_cloned)?;
+        if args.get_format() == Format::Json {
+            let report = ListReport::new(self, &dependencies);
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            return Ok(());
+        }
         let (found_config, found_bin) = (
             existence(&self.editor.config)?,
             existence(&self.editor.bin)?,
@@ -464,9 +1473,15 @@ impl Config {
         } else {
             String::new()
         };
+        let installed_editor = if dependencies.manifest.editor.is_empty() {
+            String::new()
+        } else {
+            format!("{BLUE}INSTALLED{NC} ")
+        };
         print!(
-            "Editor: {GREEN}{}{NC} {}\n\tConfiguration : {} {}\n\tBinary : {} {}\n\n",
+            "Editor: {GREEN}{}{NC} {}{}\n\tConfiguration : {} {}\n\tBinary : {} {}\n\n",
             self.editor.name,
+            installed_editor,
             error_editor,
             self.editor.config.display(),
             found_config,
@@ -475,7 +1490,7 @@ impl Config {
         );
 
         if !args.verbose {
-            print!("Tools : \n");
+            println!("Tools : ");
         }
         if let Some(tools) = self.tools.as_ref() {
             for (tool_key, tool) in tools {
@@ -488,10 +1503,36 @@ impl Config {
         }
         if let Some(groups) = self.groups.as_ref() {
             for (group_key, group) in groups {
-                group.list(&dependencies, group_key, self.tools.as_ref(), args)?;
+                group.list(
+                    &dependencies,
+                    group_key,
+                    Some(groups),
+                    self.tools.as_ref(),
+                    args,
+                )?;
             }
         }
 
+        let capabilities = self.get_capabilities();
+        if !capabilities.is_empty() {
+            print!("\nCapabilities : \n");
+        }
+        for (capability, providers) in capabilities {
+            let selected = self
+                .get_selected_provider(capability, &dependencies)
+                .map_or(format!("{YELLOW}NONE AVAILABLE{NC}"), |provider| {
+                    format!("{GREEN}{provider}{NC}")
+                });
+            println!(
+                " - {capability} : {selected} (provided by {})",
+                providers
+                    .iter()
+                    .map(|key| key.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            );
+        }
+
         if let Some(d_groups) = self.default_groups.as_ref() {
             print!("\nDefault groups : ");
             for group in d_groups {
@@ -516,11 +1557,340 @@ impl Config {
                 );
             }
         }
-        print!("\n");
+        println!();
         if !args.verbose {
-            print!("\nSee with -v (verbose mode) for more details\n");
+            println!("\nSee with -v (verbose mode) for more details");
         }
         std::io::stdout().flush()?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    /// Config with the top level `head` and `tools`, given as (key, TOML fields of the
+    /// tool), their binary is `/`
+    fn config_with(head: &str, tools: &[(&str, &str)]) -> Config {
+        let mut content =
+            format!("{head}\n[editor]\nname = \"editor\"\nbin = \"/\"\nconfig = \"/\"\n");
+        for (key, fields) in tools {
+            content.push_str(&format!(
+                "[tools.{key}]\nname = \"{key}\"\nbin = \"/\"\n{fields}\n"
+            ));
+        }
+        toml::from_str(&content).unwrap()
+    }
+
+    /// Config of `tools`, given as (key, dependencies)
+    fn config(tools: &[(&str, &[&str])]) -> Config {
+        let fields: Vec<String> = tools
+            .iter()
+            .map(|(_, dependencies)| format!("dependencies = {dependencies:?}"))
+            .collect();
+        let tools: Vec<(&str, &str)> = tools
+            .iter()
+            .zip(&fields)
+            .map(|((key, _), fields)| (*key, fields.as_str()))
+            .collect();
+        config_with("", &tools)
+    }
+
+    /// Record `key` as installed in the manifest of `config`
+    fn mark_installed(config: &mut Config, key: &str) {
+        let record = config.manifest.tools.entry(key.to_string()).or_default();
+        record.files.push(crate::manifest::ManifestEntry {
+            field: String::from("bin"),
+            path: PathBuf::from("/bin").join(key),
+            source: PathBuf::from("/"),
+            method: crate::manifest::InstallMethod::Copied,
+            hash: String::new(),
+        });
+    }
+
+    fn parse_args(tools: &[&str]) -> Args {
+        let mut argv = vec!["editor", "list"];
+        for tool in tools {
+            argv.extend(["-t", tool]);
+        }
+        Args::parse_from(argv)
+    }
+
+    #[test]
+    fn dependencies_are_ordered_before_their_dependents() {
+        let config = config(&[("a", &["b", "c"]), ("b", &["d"]), ("c", &["b"]), ("d", &[])]);
+        let args = parse_args(&["a"]);
+        let dependencies = config.get_dependencies(&args).unwrap();
+        assert_eq!(dependencies.order, ["d", "b", "c", "a"]);
+        assert_eq!(dependencies.requested, ["a"]);
+
+        // every tool when nothing is asked for, in the same order between runs
+        let args = parse_args(&[]);
+        let dependencies = config.get_dependencies(&args).unwrap();
+        assert_eq!(dependencies.order, ["d", "b", "c", "a"]);
+        let ordered: Vec<&String> = dependencies
+            .ordered_tools()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(ordered, ["d", "b", "c", "a"]);
+    }
+
+    #[test]
+    fn collisions_group_owners_of_nested_destinations() {
+        let destinations: Vec<(PathBuf, String)> = [
+            ("/bin/hx", "editor"),
+            ("/config/hx", "editor config"),
+            ("/config/hx/languages.toml", "tool a"),
+            ("/bin/ruff", "tool b"),
+            ("/bin/hx", "tool c"),
+            ("/config/hx", "tool d"),
+        ]
+        .into_iter()
+        .map(|(path, owner)| (PathBuf::from(path), owner.to_string()))
+        .collect();
+        let collisions = Config::find_collisions(&destinations);
+        assert_eq!(collisions.len(), 2);
+        assert_eq!(collisions[0].0, Path::new("/bin/hx"));
+        assert_eq!(collisions[0].1, ["editor", "tool c"]);
+        assert_eq!(collisions[1].0, Path::new("/config/hx"));
+        assert_eq!(collisions[1].1, ["editor config", "tool a", "tool d"]);
+
+        let distinct = &destinations[..2];
+        assert!(Config::find_collisions(distinct).is_empty());
+        // a common prefix of the names is not a nested path
+        let prefix = [
+            (PathBuf::from("/bin/hx"), String::from("editor")),
+            (PathBuf::from("/bin/hx-lsp"), String::from("tool")),
+        ];
+        assert!(Config::find_collisions(&prefix).is_empty());
+    }
+
+    #[test]
+    fn unavailable_optional_dependencies_leave_no_error() {
+        let config = config_with(
+            "",
+            &[
+                ("a", "optional_dependencies = [\"b\", \"missing\"]"),
+                ("b", "dependencies = [\"c\", \"missing\"]"),
+                ("c", ""),
+                ("d", "optional_dependencies = [\"c\"]"),
+            ],
+        );
+        let args = parse_args(&["a"]);
+        let dependencies = config.get_dependencies(&args).unwrap();
+        assert_eq!(dependencies.order, ["a"]);
+        // what the unavailable b resolved is not kept either
+        assert!(dependencies.unsatisfied_tools.is_empty());
+        assert!(dependencies.validate(&config, &args).is_ok());
+
+        let args = parse_args(&["d"]);
+        let dependencies = config.get_dependencies(&args).unwrap();
+        assert_eq!(dependencies.order, ["c", "d"]);
+    }
+
+    #[test]
+    fn one_of_prefers_an_installed_alternative() {
+        let mut config = config_with(
+            "",
+            &[
+                ("a", "one_of = [\"broken\", \"b\", \"c\"]"),
+                ("broken", "dependencies = [\"d\", \"missing\"]"),
+                ("b", ""),
+                ("c", ""),
+                ("d", ""),
+            ],
+        );
+        let args = parse_args(&["a"]);
+        let dependencies = config.get_dependencies(&args).unwrap();
+        // the rejected alternative and its dependencies are left out
+        assert_eq!(dependencies.order, ["b", "a"]);
+        assert!(dependencies.validate(&config, &args).is_ok());
+
+        mark_installed(&mut config, "c");
+        let dependencies = config.get_dependencies(&args).unwrap();
+        assert_eq!(dependencies.order, ["c", "a"]);
+    }
+
+    #[test]
+    fn one_of_fails_when_no_alternative_is_satisfied() {
+        let config = config_with(
+            "",
+            &[
+                ("a", "one_of = [\"b\", \"missing\"]"),
+                ("b", "dependencies = [\"missing\"]"),
+            ],
+        );
+        let args = parse_args(&["a"]);
+        let dependencies = config.get_dependencies(&args).unwrap();
+        assert!(dependencies.order.is_empty());
+        let required = dependencies.unsatisfied_tools["a"]
+            .required
+            .as_ref()
+            .unwrap();
+        assert!(
+            matches!(&required[&String::from("b")].1, DependencyErrorType::NoneAvailable(keys) if keys == &["b", "missing"])
+        );
+        assert!(dependencies.validate(&config, &args).is_err());
+    }
+
+    #[test]
+    fn capabilities_resolve_to_a_requested_or_default_provider() {
+        let tools = [
+            ("a", "dependencies = [\"lsp\"]"),
+            ("p1", "provides = [\"lsp\"]"),
+            ("p2", "provides = [\"lsp\"]"),
+        ];
+        let config = config_with("", &tools);
+        let args = parse_args(&["a"]);
+        let dependencies = config.get_dependencies(&args).unwrap();
+        assert_eq!(dependencies.providers["lsp"], "p1");
+        assert_eq!(dependencies.order, ["p1", "a"]);
+
+        let args = parse_args(&["a", "p2"]);
+        let dependencies = config.get_dependencies(&args).unwrap();
+        assert_eq!(dependencies.providers["lsp"], "p2");
+        assert!(!dependencies.order.contains(&String::from("p1")));
+
+        let config = config_with("default_tools = [\"p2\"]", &tools);
+        let args = parse_args(&["a"]);
+        let dependencies = config.get_dependencies(&args).unwrap();
+        assert_eq!(dependencies.providers["lsp"], "p2");
+        assert_eq!(dependencies.order, ["p2", "a"]);
+    }
+
+    #[test]
+    fn capabilities_fail_when_no_provider_is_satisfied() {
+        let config = config_with(
+            "",
+            &[
+                ("a", "dependencies = [\"lsp\"]"),
+                (
+                    "p1",
+                    "provides = [\"lsp\"]\ndependencies = [\"b\", \"missing\"]",
+                ),
+                ("p2", "provides = [\"lsp\"]\ndependencies = [\"missing\"]"),
+                ("b", ""),
+            ],
+        );
+        let args = parse_args(&["a"]);
+        let dependencies = config.get_dependencies(&args).unwrap();
+        // the rejected providers and their dependencies are left out
+        assert!(dependencies.order.is_empty());
+        assert!(dependencies.providers.is_empty());
+        let required = dependencies.unsatisfied_tools["a"]
+            .required
+            .as_ref()
+            .unwrap();
+        assert!(
+            matches!(&required[&String::from("lsp")].1, DependencyErrorType::NoneAvailable(keys) if keys == &["p1", "p2"])
+        );
+        assert!(dependencies.validate(&config, &args).is_err());
+    }
+
+    #[test]
+    fn conflicts_are_only_reported_between_selected_tools() {
+        let config = config_with(
+            "",
+            &[
+                ("a", "conflicts = [\"b\"]"),
+                ("b", ""),
+                ("c", "dependencies = [\"b\"]"),
+            ],
+        );
+        let args = parse_args(&["a"]);
+        let dependencies = config.get_dependencies(&args).unwrap();
+        assert!(dependencies.conflicts.is_empty());
+
+        // selected directly or pulled in as a dependency, reported once
+        for selection in [&["a", "b"][..], &["c", "a"][..]] {
+            let args = parse_args(selection);
+            let dependencies = config.get_dependencies(&args).unwrap();
+            assert_eq!(
+                dependencies.conflicts,
+                [(String::from("a"), String::from("b"))]
+            );
+            assert!(dependencies.in_conflict("b", "a"));
+        }
+    }
+
+    #[test]
+    fn conflicts_are_checked_on_the_selection_with_defaults() {
+        let config = config_with(
+            "default_tools = [\"a\"]",
+            &[("a", "conflicts = [\"b\"]"), ("b", "")],
+        );
+        // b is declared but only the default a is installed
+        let selection = config.get_selection(&parse_args(&[]));
+        let dependencies = config.get_dependencies(&selection).unwrap();
+        assert_eq!(dependencies.order, ["a"]);
+        assert!(dependencies.check_conflicts().is_ok());
+
+        // the default a is installed along with b
+        let selection = config.get_selection(&parse_args(&["b"]));
+        let dependencies = config.get_dependencies(&selection).unwrap();
+        assert!(dependencies.in_conflict("a", "b"));
+        assert!(dependencies.check_conflicts().is_err());
+    }
+
+    #[test]
+    fn env_names_must_be_variable_names() {
+        let mut config = config(&[("a", &[])]);
+        for (name, valid) in [
+            ("_FIRST_HOME2", true),
+            ("first", true),
+            ("2ND", false),
+            ("X; rm -rf ~", false),
+            ("", false),
+        ] {
+            let tool = config.tools.as_mut().unwrap().get_mut("a").unwrap();
+            tool.env = Some(BTreeMap::from([(name.to_string(), String::from("value"))]));
+            assert_eq!(config.check_env_names().is_ok(), valid, "{name}");
+        }
+    }
+
+    #[test]
+    fn cycles_are_reported_with_their_path() {
+        let config = config(&[("a", &["b"]), ("b", &["c"]), ("c", &["b"]), ("d", &[])]);
+        let args = parse_args(&["a", "d"]);
+        let dependencies = config.get_dependencies(&args).unwrap();
+        assert_eq!(dependencies.order, ["d"]);
+        let cycle = ["b", "c", "b"].map(String::from).to_vec();
+        for (key, next) in [("b", "c"), ("c", "b")] {
+            let required = dependencies.unsatisfied_tools[key]
+                .required
+                .as_ref()
+                .unwrap();
+            assert!(
+                matches!(&required[&next.to_string()].1, DependencyErrorType::Cycle(path) if *path == cycle)
+            );
+        }
+        // a tool depending on the cycle is unsatisfied without being part of it
+        let required = dependencies.unsatisfied_tools["a"]
+            .required
+            .as_ref()
+            .unwrap();
+        assert!(matches!(
+            required[&String::from("b")].1,
+            DependencyErrorType::UnSatisfiedDepencies
+        ));
+        assert!(dependencies.validate(&config, &args).is_err());
+    }
+
+    #[test]
+    fn self_dependency_is_a_cycle() {
+        let config = config(&[("a", &["a"])]);
+        let args = parse_args(&["a"]);
+        let dependencies = config.get_dependencies(&args).unwrap();
+        assert!(dependencies.order.is_empty());
+        let required = dependencies.unsatisfied_tools["a"]
+            .required
+            .as_ref()
+            .unwrap();
+        assert!(
+            matches!(&required[&String::from("a")].1, DependencyErrorType::Cycle(path) if path == &["a", "a"])
+        );
+    }
+}
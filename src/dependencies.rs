@@ -1,16 +1,20 @@
 use crate::{
     args::Args,
-    utils::{NC, RED, YELLOW},
+    utils::{GREEN, NC, RED, YELLOW},
 };
 use anyhow::{anyhow, Result};
 use std::{collections::HashMap, fmt::Display, io::Write, path::PathBuf};
 use toml::toml;
 
-use crate::{config::Config, group::Group, tool::Tool};
+use crate::{config::Config, graph::Edge, group::Group, manifest::Manifest, tool::Tool};
 #[derive(Debug, Clone)]
 pub enum DependencyErrorType {
     NotFound,
     UnSatisfiedDepencies,
+    /// Keys of the tools forming the cycle, the first one repeated at the end
+    Cycle(Vec<String>),
+    /// None of the alternatives of `one_of` can be satisfied
+    NoneAvailable(Vec<String>),
 }
 
 impl Display for DependencyErrorType {
@@ -18,8 +22,15 @@ impl Display for DependencyErrorType {
         f.write_fmt(format_args!(
             "{}",
             match self {
-                DependencyErrorType::NotFound => "is not found",
-                DependencyErrorType::UnSatisfiedDepencies => "contains invalid dependencies",
+                DependencyErrorType::NotFound => String::from("is not found"),
+                DependencyErrorType::UnSatisfiedDepencies =>
+                    String::from("contains invalid dependencies"),
+                DependencyErrorType::Cycle(path) =>
+                    format!("is part of a dependency cycle: {}", path.join(" -> ")),
+                DependencyErrorType::NoneAvailable(keys) => format!(
+                    "has none of its alternatives available: {}",
+                    keys.join(", ")
+                ),
             }
         ))
     }
@@ -30,33 +41,68 @@ impl DependencyErrorType {
         match self {
             DependencyErrorType::NotFound => format!("{RED}NOT FOUND{NC}"),
             DependencyErrorType::UnSatisfiedDepencies => format!("{YELLOW}INVALID DEPENDENCY{NC}"),
+            DependencyErrorType::Cycle(path) => format!("{RED}CYCLE{NC} ({})", path.join(" -> ")),
+            DependencyErrorType::NoneAvailable(_) => format!("{RED}NONE AVAILABLE{NC}"),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct UnSatisfiedTool<'l> {
     pub tool: &'l Tool,
     pub required: Option<HashMap<&'l String, (Tool, DependencyErrorType)>>,
     pub paths: Option<HashMap<String, &'l PathBuf>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct UnSatisfiedGroup<'l> {
     pub group: &'l Group,
     pub unsatisfied_tools: Vec<&'l str>,
+    /// Group keys forming a cycle through included groups
+    pub cycle: Option<Vec<String>>,
 }
 
-#[derive(Default, Debug)]
+#[derive(Debug, Clone)]
 pub struct Dependencies<'l> {
     pub checked_tool_keys: Vec<&'l String>,
+    /// Tools whose dependencies are being resolved, from the outermost one
+    pub visiting_tool_keys: Vec<&'l String>,
     pub satisfied_tools: HashMap<String, &'l Tool>,
     pub satisfied_groups: HashMap<String, &'l Group>,
     pub unsatisfied_tools: HashMap<String, UnSatisfiedTool<'l>>,
     pub unsatisfied_groups: HashMap<String, UnSatisfiedGroup<'l>>,
+    pub manifest: &'l Manifest,
+    /// Keys of the tools asked for, the other ones are only dependencies
+    pub requested: Vec<String>,
+    /// Capability -> key of the tool chosen to provide it
+    pub providers: HashMap<String, String>,
+    /// Keys of satisfied tools, each one after its dependencies
+    pub order: Vec<String>,
+    /// Tool key -> dependencies the resolver went through, only the chosen alternative
+    pub edges: HashMap<String, Vec<(&'l String, Edge)>>,
+    /// Selected tools declaring a conflict with another selected one, only refused when
+    /// installing
+    pub conflicts: Vec<(String, String)>,
 }
 
 impl<'l> Dependencies<'l> {
+    pub fn new(manifest: &'l Manifest) -> Dependencies<'l> {
+        Dependencies {
+            checked_tool_keys: Vec::new(),
+            visiting_tool_keys: Vec::new(),
+            satisfied_tools: HashMap::new(),
+            satisfied_groups: HashMap::new(),
+            unsatisfied_tools: HashMap::new(),
+            unsatisfied_groups: HashMap::new(),
+            manifest,
+            requested: Vec::new(),
+            providers: HashMap::new(),
+            order: Vec::new(),
+            edges: HashMap::new(),
+            conflicts: Vec::new(),
+        }
+    }
+
     pub fn validate(&self, config: &Config, args: &Args) -> Result<()> {
         if !self.unsatisfied_tools.is_empty() || !self.unsatisfied_groups.is_empty() {
             self.print_unsatisfied_dependencies(config, args)?;
@@ -70,14 +116,108 @@ impl<'l> Dependencies<'l> {
             tool.tool.list(self, tool_key, args)?;
         }
         for (group_key, group) in &self.unsatisfied_groups {
-            group
-                .group
-                .list(self, &group_key, config.tools.as_ref(), args)?;
+            group.group.list(
+                self,
+                &group_key,
+                config.groups.as_ref(),
+                config.tools.as_ref(),
+                args,
+            )?;
         }
         std::io::stdout().flush()?;
         Ok(())
     }
 
+    /// Mark `tool_key` as unsatisfied because of its dependency `dep_key`
+    pub(crate) fn add_required_error(
+        &mut self,
+        tool_key: &'l String,
+        tool: &'l Tool,
+        dep_key: &'l String,
+        error: DependencyErrorType,
+    ) {
+        let required = (
+            Tool {
+                name: dep_key.to_owned(),
+                ..Default::default()
+            },
+            error,
+        );
+        self.unsatisfied_tools
+            .entry(tool_key.to_string())
+            .or_insert(UnSatisfiedTool {
+                tool,
+                required: Some(HashMap::new()),
+                paths: None,
+            })
+            .required
+            .get_or_insert(HashMap::new())
+            .entry(dep_key)
+            .or_insert(required);
+    }
+
+    /// Both tools are selected while one of them declares a conflict with the other
+    pub(crate) fn in_conflict(&self, tool_key: &str, other_key: &str) -> bool {
+        self.conflicts.iter().any(|(one, two)| {
+            (one == tool_key && two == other_key) || (one == other_key && two == tool_key)
+        })
+    }
+
+    /// Refuse to install tools in conflict with each other
+    pub fn check_conflicts(&self) -> Result<()> {
+        if self.conflicts.is_empty() {
+            return Ok(());
+        }
+        println!("\nThe following tools cannot be installed together :\n");
+        for (tool_key, other_key) in &self.conflicts {
+            println!(" - {GREEN}{tool_key}{NC} conflicts with {GREEN}{other_key}{NC}");
+        }
+        Err(anyhow!(
+            "Tools in conflict are selected, choose one of them with --tools"
+        ))
+    }
+
+    /// `key` is a satisfied tool or a capability provided by one
+    pub(crate) fn is_satisfied(&self, key: &String) -> bool {
+        self.satisfied_tools.contains_key(key)
+            || self
+                .providers
+                .get(key)
+                .is_some_and(|provider| self.satisfied_tools.contains_key(provider))
+    }
+
+    /// Status of an optional dependency or an alternative
+    pub(crate) fn get_availability(&self, tool_key: &String) -> String {
+        if self.is_satisfied(tool_key) {
+            String::new()
+        } else {
+            format!("{YELLOW}UNAVAILABLE{NC}")
+        }
+    }
+
+    /// Warn about the optional dependencies of the selected tools that will not be installed
+    pub(crate) fn print_optional_warnings(&self) -> Result<()> {
+        for (tool_key, tool) in self.ordered_tools() {
+            for dep in tool.optional_dependencies.iter().flatten() {
+                if !self.is_satisfied(dep) {
+                    println!(
+                        "{YELLOW}WARNING{NC}: Optional dependency '{dep}' of '{tool_key}' is not available, it will not be installed"
+                    );
+                }
+            }
+        }
+        Ok(std::io::stdout().flush()?)
+    }
+
+    /// Satisfied tools in installation order, reverse it to remove them
+    pub fn ordered_tools(&self) -> Vec<(&String, &'l Tool)> {
+        self.order
+            .iter()
+            .filter_map(|key| self.satisfied_tools.get_key_value(key))
+            .map(|(key, tool)| (key, *tool))
+            .collect()
+    }
+
     pub(crate) fn get_error_dependencies(
         &self,
         tool_key: &String,
@@ -126,7 +266,11 @@ impl<'l> Dependencies<'l> {
     }
 
     pub(crate) fn list_tools_for_action(&self, label: &str, after: &str) -> Result<()> {
-        self.list_for_action(label, after, self.satisfied_tools.keys());
+        self.list_for_action(
+            label,
+            after,
+            self.ordered_tools().into_iter().map(|(k, _)| k),
+        );
         Ok(std::io::stdout().flush()?)
     }
 
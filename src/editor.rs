@@ -1,8 +1,8 @@
 use crate::package::Package;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub struct Editor {
     pub name: String,
     pub config: PathBuf,
@@ -0,0 +1,492 @@
+use crate::args::Args;
+use crate::config::Config;
+use crate::dependencies::Dependencies;
+use crate::utils::{BLUE, CYAN, GREEN, NC, RED, YELLOW};
+use anyhow::{anyhow, Result};
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io::Write;
+
+/// A tool, a group or a capability provided by tools of the configuration
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Node<'l> {
+    Tool(&'l String),
+    Group(&'l String),
+    Capability(&'l String),
+}
+
+impl<'l> Node<'l> {
+    pub fn key(&self) -> &'l String {
+        match self {
+            Node::Tool(key) | Node::Group(key) | Node::Capability(key) => key,
+        }
+    }
+}
+
+impl Display for Node<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Node::Tool(key) => f.write_fmt(format_args!("{GREEN}{key}{NC}")),
+            Node::Group(key) => f.write_fmt(format_args!("{GREEN}{key}{NC} (group)")),
+            Node::Capability(key) => f.write_fmt(format_args!("{GREEN}{key}{NC} (capability)")),
+        }
+    }
+}
+
+/// How a node refers to one of its dependencies
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edge {
+    Required,
+    Optional,
+    /// One of the `one_of` alternatives
+    Alternative,
+    /// From a capability to a tool providing it
+    Provider,
+}
+
+impl Edge {
+    pub fn label(&self) -> Option<&'static str> {
+        match self {
+            Edge::Required => None,
+            Edge::Optional => Some("optional"),
+            Edge::Alternative => Some("one of"),
+            Edge::Provider => Some("provided by"),
+        }
+    }
+}
+
+impl Config {
+    /// Find `key` among tools first, then among groups, then among capabilities
+    pub(crate) fn get_node(&self, key: &str) -> Option<Node<'_>> {
+        self.tools
+            .as_ref()
+            .and_then(|tools| tools.get_key_value(key))
+            .map(|(key, _)| Node::Tool(key))
+            .or_else(|| {
+                self.groups
+                    .as_ref()
+                    .and_then(|groups| groups.get_key_value(key))
+                    .map(|(key, _)| Node::Group(key))
+            })
+            .or_else(|| self.get_capability_node(key))
+    }
+
+    fn get_capability_node(&self, key: &str) -> Option<Node<'_>> {
+        self.tools
+            .iter()
+            .flatten()
+            .flat_map(|(_, tool)| tool.provides.iter().flatten())
+            .find(|capability| *capability == key)
+            .map(Node::Capability)
+    }
+
+    /// Dependencies of `node` as written in the configuration, with what they refer to
+    pub(crate) fn get_children<'l>(
+        &'l self,
+        node: Node<'l>,
+    ) -> Vec<(&'l String, Option<Node<'l>>, Edge)> {
+        match node {
+            Node::Tool(key) => {
+                let Some(tool) = self.tools.as_ref().and_then(|tools| tools.get(key)) else {
+                    return Vec::new();
+                };
+                [
+                    (&tool.dependencies, Edge::Required),
+                    (&tool.optional_dependencies, Edge::Optional),
+                    (&tool.one_of, Edge::Alternative),
+                ]
+                .into_iter()
+                .flat_map(|(deps, edge)| deps.iter().flatten().map(move |dep| (dep, edge)))
+                .map(|(dep, edge)| {
+                    let tool = self
+                        .tools
+                        .as_ref()
+                        .and_then(|tools| tools.get_key_value(dep))
+                        .map(|(key, _)| Node::Tool(key));
+                    (dep, tool.or_else(|| self.get_capability_node(dep)), edge)
+                })
+                .collect()
+            }
+            Node::Capability(key) => self
+                .get_providers(key)
+                .into_iter()
+                .map(|provider| (provider, Some(Node::Tool(provider)), Edge::Provider))
+                .collect(),
+            Node::Group(key) => self
+                .groups
+                .as_ref()
+                .and_then(|groups| groups.get(key))
+                .map(|group| &group.dependencies)
+                .into_iter()
+                .flatten()
+                .map(|dep| (dep, self.get_node(dep), Edge::Required))
+                .collect(),
+        }
+    }
+
+    /// Dependencies of `node` chosen by the resolver, the configured ones for groups
+    fn get_resolved_children<'l>(
+        &'l self,
+        dependencies: &Dependencies<'l>,
+        node: Node<'l>,
+    ) -> Vec<(&'l String, Option<Node<'l>>, Edge)> {
+        match node {
+            Node::Tool(key) => dependencies
+                .edges
+                .get(key)
+                .into_iter()
+                .flatten()
+                .map(|(dep, edge)| {
+                    let tool = self
+                        .tools
+                        .as_ref()
+                        .and_then(|tools| tools.get_key_value(*dep))
+                        .map(|(key, _)| Node::Tool(key));
+                    (*dep, tool.or_else(|| self.get_capability_node(dep)), *edge)
+                })
+                .collect(),
+            Node::Capability(key) => match dependencies
+                .providers
+                .get(key)
+                .and_then(|provider| self.tools.as_ref()?.get_key_value(provider))
+            {
+                Some((provider, _)) => vec![(provider, Some(Node::Tool(provider)), Edge::Provider)],
+                None => self.get_children(node),
+            },
+            Node::Group(_) => self.get_children(node),
+        }
+    }
+
+    /// Shortest chain of dependencies chosen by the resolver going from `from` to `to`
+    fn find_path<'l>(
+        &'l self,
+        dependencies: &Dependencies<'l>,
+        from: Node<'l>,
+        to: Node<'l>,
+    ) -> Option<Vec<Node<'l>>> {
+        let mut visited = vec![from];
+        let mut queue = VecDeque::from([vec![from]]);
+        while let Some(path) = queue.pop_front() {
+            let last = *path.last()?;
+            if last == to {
+                return Some(path);
+            }
+            for (_, child, _) in self.get_resolved_children(dependencies, last) {
+                if let Some(child) = child.filter(|child| !visited.contains(child)) {
+                    visited.push(child);
+                    let mut next = path.clone();
+                    next.push(child);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    fn get_target(&self, args: &Args) -> Result<Option<Node<'_>>> {
+        args.target
+            .as_ref()
+            .map(|target| {
+                self.get_node(target).ok_or(anyhow!(
+                    "'{target}' is neither a tool nor a group of the configuration"
+                ))
+            })
+            .transpose()
+    }
+
+    /// Print the dependency tree of the target, or of what would be installed
+    pub fn tree(&self, args: &Args) -> Result<()> {
+        let target = self.get_target(args)?;
+        let mut selection = self.get_selection(args);
+        let roots: Vec<Node> = match target {
+            Some(node) => {
+                // resolved as if it was installed along with the selection
+                match node {
+                    Node::Tool(key) => selection
+                        .tools
+                        .get_or_insert_with(Vec::new)
+                        .push(key.to_owned()),
+                    Node::Group(key) => selection
+                        .groups
+                        .get_or_insert_with(Vec::new)
+                        .push(key.to_owned()),
+                    // a capability is resolved when a selected tool or group needs it
+                    Node::Capability(_) => (),
+                }
+                vec![node]
+            }
+            None => {
+                let mut roots: Vec<Node> = selection
+                    .groups
+                    .iter()
+                    .flatten()
+                    .filter_map(|key| self.get_node(key).filter(|n| matches!(n, Node::Group(_))))
+                    .chain(selection.tools.iter().flatten().filter_map(|key| {
+                        self.get_node(key).filter(|n| matches!(n, Node::Tool(_)))
+                    }))
+                    .collect();
+                roots.dedup();
+                // nothing required means every tool
+                if roots.is_empty() {
+                    roots = self
+                        .tools
+                        .iter()
+                        .flatten()
+                        .map(|(key, _)| Node::Tool(key))
+                        .collect();
+                    roots.sort_by_key(|node| node.key());
+                }
+                roots
+            }
+        };
+
+        let dependencies = self.get_dependencies(&selection)?;
+        for root in roots {
+            println!("{root}{}", Config::get_node_status(&dependencies, root));
+            self.print_tree(&dependencies, root, &mut vec![], 1);
+        }
+        std::io::stdout().flush()?;
+        Ok(())
+    }
+
+    fn get_node_status(dependencies: &Dependencies, node: Node) -> String {
+        let Node::Tool(key) = node else {
+            return String::new();
+        };
+        let mut status = String::new();
+        if dependencies.manifest.is_installed(key) {
+            status += &format!(" {BLUE}INSTALLED{NC}");
+        }
+        if dependencies.as_errors(key) {
+            status += &format!(" {CYAN}ERROR{NC}");
+        }
+        status
+    }
+
+    fn print_tree<'l>(
+        &'l self,
+        dependencies: &Dependencies<'l>,
+        node: Node<'l>,
+        visiting: &mut Vec<Node<'l>>,
+        depth: usize,
+    ) {
+        let indent = "  ".repeat(depth);
+        visiting.push(node);
+        for (key, child, edge) in self.get_resolved_children(dependencies, node) {
+            let kind = edge
+                .label()
+                .map_or(String::new(), |label| format!(" ({label})"));
+            match child {
+                None => println!("{indent}- {key}{kind} {RED}NOT FOUND{NC}"),
+                Some(child) if visiting.contains(&child) => {
+                    println!("{indent}- {child}{kind} {RED}CYCLE{NC}")
+                }
+                Some(child) => {
+                    println!(
+                        "{indent}- {child}{kind}{}",
+                        Config::get_node_status(dependencies, child)
+                    );
+                    self.print_tree(dependencies, child, visiting, depth + 1);
+                }
+            }
+        }
+        visiting.pop();
+    }
+
+    /// Print tools and groups as a Graphviz digraph, invalid ones in red
+    pub fn graph(&self, args: &Args) -> Result<()> {
+        let args_cloned = args.clone_with_everything(self);
+        let dependencies = self.get_dependencies(&args_cloned)?;
+        let mut nodes: Vec<Node> = self
+            .tools
+            .iter()
+            .flatten()
+            .map(|(key, _)| Node::Tool(key))
+            .chain(
+                self.groups
+                    .iter()
+                    .flatten()
+                    .map(|(key, _)| Node::Group(key)),
+            )
+            .chain(self.get_capabilities().into_keys().map(Node::Capability))
+            .collect();
+        nodes.sort_by_key(|node| {
+            let rank = match node {
+                Node::Tool(_) => 0,
+                Node::Capability(_) => 1,
+                Node::Group(_) => 2,
+            };
+            (rank, node.key())
+        });
+
+        let mut lines = Vec::new();
+        let mut missing: Vec<&String> = Vec::new();
+        for node in &nodes {
+            let (shape, invalid) = match node {
+                Node::Tool(key) => ("box", dependencies.as_errors(key)),
+                Node::Group(key) => (
+                    "ellipse",
+                    dependencies.unsatisfied_groups.contains_key(*key),
+                ),
+                Node::Capability(key) => (
+                    "diamond",
+                    self.get_selected_provider(key, &dependencies).is_none(),
+                ),
+            };
+            let color = if invalid {
+                ", color=red, fontcolor=red"
+            } else {
+                ""
+            };
+            lines.push(format!(
+                "    {} [label={}, shape={shape}{color}];",
+                Config::get_dot_id(*node),
+                Config::quote_dot(node.key())
+            ));
+        }
+        for node in &nodes {
+            for (key, child, edge) in self.get_children(*node) {
+                let to = match child {
+                    Some(child) => Config::get_dot_id(child),
+                    None => {
+                        if !missing.contains(&key) {
+                            missing.push(key);
+                        }
+                        Config::quote_dot(&format!("missing:{key}"))
+                    }
+                };
+                let style = edge.label().map_or(String::new(), |label| {
+                    format!(" [label={}, style=dashed]", Config::quote_dot(label))
+                });
+                lines.push(format!("    {} -> {to}{style};", Config::get_dot_id(*node)));
+            }
+        }
+        for key in missing {
+            lines.push(format!(
+                "    {} [label={}, shape=box, style=dashed, color=red, fontcolor=red];",
+                Config::quote_dot(&format!("missing:{key}")),
+                Config::quote_dot(key)
+            ));
+        }
+
+        // dot is the only format of the graph action, checked with the arguments
+        println!(
+            "digraph editor {{\n    rankdir=LR;\n{}\n}}",
+            lines.join("\n")
+        );
+        Ok(())
+    }
+
+    fn get_dot_id(node: Node) -> String {
+        match node {
+            Node::Tool(key) => Config::quote_dot(&format!("tool:{key}")),
+            Node::Group(key) => Config::quote_dot(&format!("group:{key}")),
+            Node::Capability(key) => Config::quote_dot(&format!("capability:{key}")),
+        }
+    }
+
+    fn quote_dot(value: &str) -> String {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+
+    /// Explain which defaults, groups and tools make the target tool selected
+    pub fn why(&self, args: &Args) -> Result<()> {
+        let target = match self.get_target(args)? {
+            Some(node @ Node::Tool(_)) => node,
+            _ => {
+                return Err(anyhow!(
+                    "'{}' is not a tool of the configuration",
+                    args.target.as_deref().unwrap_or_default()
+                ))
+            }
+        };
+        let format_path = |path: Vec<Node>| {
+            path.iter()
+                .map(|node| node.to_string())
+                .collect::<Vec<String>>()
+                .join(" -> ")
+        };
+
+        let selection = self.get_selection(args);
+        let dependencies = self.get_dependencies(&selection)?;
+        let label = |key: &String, given: Option<&Vec<String>>, kind: &str| {
+            if given.is_some_and(|given| given.contains(key)) {
+                kind.to_string()
+            } else {
+                format!("default {kind}")
+            }
+        };
+
+        let mut reasons = Vec::new();
+        let mut tools: Vec<&String> = selection.tools.iter().flatten().collect();
+        tools.sort();
+        tools.dedup();
+        for key in tools {
+            if let Some(path) = self
+                .get_node(key)
+                .and_then(|node| self.find_path(&dependencies, node, target))
+            {
+                let label = label(key, args.tools.as_ref(), "tool");
+                reasons.push(format!("{label} : {}", format_path(path)));
+            }
+        }
+        let mut groups: Vec<&String> = selection.groups.iter().flatten().collect();
+        groups.sort();
+        groups.dedup();
+        for key in groups {
+            if let Some(path) = self
+                .get_node(key)
+                .filter(|node| matches!(node, Node::Group(_)))
+                .and_then(|node| self.find_path(&dependencies, node, target))
+            {
+                let label = label(key, args.groups.as_ref(), "group");
+                reasons.push(format!("{label} : {}", format_path(path)));
+            }
+        }
+        if selection.tools.is_none() && selection.groups.is_none() {
+            reasons.push(String::from(
+                "every tool is selected when no tool or group is given",
+            ));
+            let mut dependents: Vec<&String> = self
+                .tools
+                .iter()
+                .flatten()
+                .map(|(key, _)| key)
+                .filter(|key| *key != target.key())
+                .collect();
+            dependents.sort();
+            for key in dependents {
+                if let Some(path) = self.find_path(&dependencies, Node::Tool(key), target) {
+                    reasons.push(format!("required by : {}", format_path(path)));
+                }
+            }
+        }
+        let selected = !reasons.is_empty();
+
+        if let Some(record) = self.manifest.tools.get(target.key().as_str()) {
+            if !record.is_empty() {
+                reasons.push(String::from(if record.auto {
+                    "installed as a dependency"
+                } else {
+                    "installed on demand"
+                }));
+            }
+        }
+
+        if !selected {
+            if args.tools.is_some() || args.groups.is_some() {
+                println!(
+                    "{YELLOW}WARNING{NC}: {target} is not required by the given tools and groups"
+                );
+            } else {
+                println!("{YELLOW}WARNING{NC}: {target} is only selected when given with --tools");
+            }
+        } else {
+            println!("Why {target} is selected :");
+        }
+        for reason in reasons {
+            println!(" - {reason}");
+        }
+        Ok(())
+    }
+}
@@ -4,57 +4,194 @@ use crate::{
     args::Args,
     dependencies::Dependencies,
     tool::Tool,
-    utils::{CYAN, GREEN, NC, YELLOW},
+    utils::{CYAN, GREEN, NC, RED, YELLOW},
 };
 use anyhow::Result;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub struct Group {
     pub name: String,
+    /// Tool keys or keys of other groups, a tool key wins when both exist
     pub dependencies: Vec<String>,
 }
+
+/// Tools of a group once the groups it includes are replaced by their tools
+#[derive(Debug, Default)]
+pub struct ExpandedGroup<'l> {
+    /// Tool keys in order of appearance, keys found nowhere are kept to be reported
+    pub tools: Vec<&'l String>,
+    /// Group keys forming a cycle, the first one repeated at the end
+    pub cycle: Option<Vec<String>>,
+}
+
 impl Group {
+    /// Expand `group_key` recursively into the tools it requires
+    pub(crate) fn expand<'l>(
+        group_key: &'l String,
+        groups: &'l HashMap<String, Group>,
+        tools: Option<&HashMap<String, Tool>>,
+    ) -> ExpandedGroup<'l> {
+        let mut expanded = ExpandedGroup::default();
+        Group::expand_rec(group_key, groups, tools, &mut vec![], &mut expanded);
+        expanded
+    }
+
+    fn expand_rec<'l>(
+        group_key: &'l String,
+        groups: &'l HashMap<String, Group>,
+        tools: Option<&HashMap<String, Tool>>,
+        visiting: &mut Vec<&'l String>,
+        expanded: &mut ExpandedGroup<'l>,
+    ) {
+        let Some(group) = groups.get(group_key) else {
+            return;
+        };
+        visiting.push(group_key);
+        for key in &group.dependencies {
+            if tools.is_some_and(|tools| tools.contains_key(key)) || !groups.contains_key(key) {
+                if !expanded.tools.contains(&key) {
+                    expanded.tools.push(key);
+                }
+            } else if let Some(position) = visiting.iter().position(|k| *k == key) {
+                if expanded.cycle.is_none() {
+                    expanded.cycle = Some(
+                        visiting[position..]
+                            .iter()
+                            .chain([&key])
+                            .map(|k| k.to_string())
+                            .collect(),
+                    );
+                }
+            } else {
+                Group::expand_rec(key, groups, tools, visiting, expanded);
+            }
+        }
+        visiting.pop();
+    }
+
     pub(crate) fn list(
         &self,
         dependencies: &Dependencies,
         group_key: &String,
+        groups: Option<&HashMap<String, Group>>,
         tools: Option<&HashMap<String, Tool>>,
         args: &Args,
     ) -> Result<()> {
-        let dep_errors: Vec<(&String, String)> = self
-            .dependencies
-            .iter()
-            .map(|key| {
-                (
-                    key,
-                    if tools.as_ref().is_some_and(|tools| tools.contains_key(key))
-                        && !dependencies.unsatisfied_tools.contains_key(key)
-                    {
-                        String::new()
-                    } else {
-                        format!("{YELLOW}ERROR{NC}")
-                    },
-                )
-            })
-            .collect();
-        let group_error = if dep_errors.iter().any(|(_, s)| !s.is_empty()) {
+        let tool_error = |key: &String| {
+            let capability = !tools.is_some_and(|tools| tools.contains_key(key));
+            if (capability && dependencies.is_satisfied(key))
+                || (!capability && !dependencies.unsatisfied_tools.contains_key(key))
+            {
+                String::new()
+            } else {
+                format!("{YELLOW}ERROR{NC}")
+            }
+        };
+        let expanded = groups.map(|groups| Group::expand(group_key, groups, tools));
+        let cycle_error = expanded
+            .as_ref()
+            .and_then(|e| e.cycle.as_ref())
+            .map(|path| format!(" {RED}CYCLE{NC} ({})", path.join(" -> ")))
+            .unwrap_or_default();
+        let has_errors = !cycle_error.is_empty()
+            || match expanded.as_ref() {
+                Some(expanded) => expanded.tools.iter().any(|key| !tool_error(key).is_empty()),
+                None => self
+                    .dependencies
+                    .iter()
+                    .any(|key| !tool_error(key).is_empty()),
+            };
+        let group_error = if has_errors {
             format!("{CYAN}ERROR{NC}")
         } else {
             String::new()
         };
         if args.verbose {
             print!(
-                "Group : {GREEN}{}{NC} {}\n\tDependencies : \n",
-                group_key, group_error
+                "Group : {GREEN}{}{NC} {}{}\n\tDependencies : \n",
+                group_key, group_error, cycle_error
             );
-            for (key, error) in &dep_errors {
-                print!("\t - {key} {error}\n");
-            }
+            self.list_tree(group_key, groups, tools, &tool_error, &mut vec![], 1);
         } else {
-            print!(" - {GREEN}{group_key}{NC} {group_error}\n");
+            println!(" - {GREEN}{group_key}{NC} {group_error}{cycle_error}");
         }
         std::io::stdout().flush()?;
         Ok(())
     }
+
+    /// Print dependencies, the included groups followed by their own tools
+    fn list_tree<'l, F: Fn(&String) -> String>(
+        &'l self,
+        group_key: &'l String,
+        groups: Option<&'l HashMap<String, Group>>,
+        tools: Option<&HashMap<String, Tool>>,
+        tool_error: &F,
+        visiting: &mut Vec<&'l String>,
+        depth: usize,
+    ) {
+        let indent = format!("\t{}", "  ".repeat(depth - 1));
+        visiting.push(group_key);
+        for key in &self.dependencies {
+            let included = groups
+                .filter(|_| !tools.is_some_and(|tools| tools.contains_key(key)))
+                .and_then(|groups| groups.get(key));
+            match included {
+                Some(_) if visiting.contains(&key) => {
+                    println!("{indent} - {key} (group) {RED}CYCLE{NC}");
+                }
+                Some(group) => {
+                    println!("{indent} - {key} (group)");
+                    group.list_tree(key, groups, tools, tool_error, visiting, depth + 1);
+                }
+                None => println!("{indent} - {key} {}", tool_error(key)),
+            }
+        }
+        visiting.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn groups(groups: &[(&str, &[&str])]) -> HashMap<String, Group> {
+        groups
+            .iter()
+            .map(|(key, dependencies)| {
+                let group = Group {
+                    name: key.to_string(),
+                    dependencies: dependencies.iter().map(|d| d.to_string()).collect(),
+                };
+                (key.to_string(), group)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn expand_replaces_included_groups_by_their_tools() {
+        let groups = groups(&[
+            ("all", &["web", "fmt", "rust"]),
+            ("web", &["html", "fmt"]),
+            ("rust", &["rust-analyzer"]),
+        ]);
+        let key = String::from("all");
+        let expanded = Group::expand(&key, &groups, None);
+        assert_eq!(expanded.tools, ["html", "fmt", "rust-analyzer"]);
+        assert!(expanded.cycle.is_none());
+
+        // a tool key wins over a group key
+        let tools = HashMap::from([(String::from("rust"), Tool::default())]);
+        let expanded = Group::expand(&key, &groups, Some(&tools));
+        assert_eq!(expanded.tools, ["html", "fmt", "rust"]);
+    }
+
+    #[test]
+    fn expand_reports_cycles_between_groups() {
+        let groups = groups(&[("a", &["x", "b"]), ("b", &["y", "c"]), ("c", &["a", "z"])]);
+        let key = String::from("a");
+        let expanded = Group::expand(&key, &groups, None);
+        assert_eq!(expanded.tools, ["x", "y", "z"]);
+        assert_eq!(expanded.cycle.unwrap(), ["a", "b", "c", "a"]);
+    }
 }
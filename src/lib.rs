@@ -1,8 +1,15 @@
 pub mod args;
+pub mod backup;
 pub mod config;
 pub mod dependencies;
 pub mod editor;
+pub mod graph;
 pub mod group;
+pub mod manifest;
 pub mod package;
+pub mod report;
+pub mod shell;
+pub mod status;
 pub mod tool;
+pub mod transaction;
 pub mod utils;
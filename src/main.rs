@@ -15,12 +15,18 @@ fn main() {
                     Action::Install => config.install(&args),
                     Action::Remove => config.remove(&args),
                     Action::List => config.list(&args),
-                    Action::Update => todo!(),
+                    Action::Update => config.update(&args),
+                    Action::Restore => config.restore(&args),
+                    Action::Status => config.status(&args),
+                    Action::Autoremove => config.autoremove(&args),
+                    Action::Tree => config.tree(&args),
+                    Action::Why => config.why(&args),
+                    Action::Graph => config.graph(&args),
                 };
 
                 if let Err(e) = res {
                     if !e.to_string().is_empty() {
-                        eprintln!("{RED}ERROR{NC}: {e}");
+                        eprintln!("{RED}ERROR{NC}: {e:#}");
                     }
                 }
             }
@@ -0,0 +1,154 @@
+use crate::args::Args;
+use crate::package::{Package, PackageInfo, PackageResult};
+use crate::utils::{hash_path, InstallDirs};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_FILENAME: &str = "editor-manifest.toml";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum InstallMethod {
+    Copied,
+    Linked,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ManifestEntry {
+    /// "bin", "lib" or "config"
+    pub field: String,
+    pub path: PathBuf,
+    pub source: PathBuf,
+    pub method: InstallMethod,
+    /// sha256 of the source at installation time
+    pub hash: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ManifestRecord {
+    #[serde(default)]
+    pub files: Vec<ManifestEntry>,
+    /// Installed only because another tool depends on it
+    #[serde(default)]
+    pub auto: bool,
+}
+
+/// What has been put on disk, stored in the state directory
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Manifest {
+    #[serde(default)]
+    pub editor: ManifestRecord,
+    #[serde(default)]
+    pub tools: HashMap<String, ManifestRecord>,
+}
+
+impl ManifestRecord {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn get(&self, field: &str) -> Option<&ManifestEntry> {
+        self.files.iter().find(|entry| entry.field == field)
+    }
+
+    /// Register the files of a package that have just been installed
+    pub fn record<P: Package>(
+        &mut self,
+        package: &P,
+        info: &PackageInfo,
+        args: &Args,
+        dirs: &InstallDirs,
+    ) -> Result<()> {
+        if args.dry_run {
+            return Ok(());
+        }
+        let targets = package.get_install_targets(args, dirs)?;
+        for target in &targets {
+            // lib is installed along with bin
+            let res = if target.field == "config" {
+                &info.1
+            } else {
+                &info.0
+            };
+            if !matches!(res, PackageResult::Installed | PackageResult::Linked) {
+                continue;
+            }
+            // a bin linked into the lib of the package points to where the lib has
+            // just been installed
+            let source = match targets.iter().find(|lib| lib.field == "lib") {
+                Some(lib) if target.source.starts_with(&lib.destination) => {
+                    dirs.staged(&target.source)
+                }
+                _ => target.source.to_owned(),
+            };
+            let entry = ManifestEntry {
+                field: target.field.to_string(),
+                hash: hash_path(source)?,
+                path: target.destination.to_owned(),
+                source: target.source.to_owned(),
+                method: if target.linked {
+                    InstallMethod::Linked
+                } else {
+                    InstallMethod::Copied
+                },
+            };
+            match self.files.iter_mut().find(|e| e.field == entry.field) {
+                Some(old) => *old = entry,
+                None => self.files.push(entry),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Manifest {
+    /// Nothing installed anymore, neither the editor nor any tool
+    pub fn is_empty(&self) -> bool {
+        self.editor.is_empty() && self.tools.values().all(|record| record.is_empty())
+    }
+
+    pub fn get_path(dirs: &InstallDirs) -> PathBuf {
+        dirs.staged(dirs.state.join(MANIFEST_FILENAME))
+    }
+
+    /// Stop managing what is at `path` on disk, as a file of the user put back in place
+    pub fn forget<P: AsRef<Path>>(&mut self, path: P, dirs: &InstallDirs) {
+        let path = path.as_ref();
+        for record in std::iter::once(&mut self.editor).chain(self.tools.values_mut()) {
+            record
+                .files
+                .retain(|entry| dirs.staged(&entry.path) != path);
+        }
+    }
+
+    pub fn load(dirs: &InstallDirs) -> Result<Manifest> {
+        let path = Manifest::get_path(dirs);
+        if path.exists() {
+            Ok(toml::from_str(&std::fs::read_to_string(path)?)?)
+        } else {
+            Ok(Manifest::default())
+        }
+    }
+
+    /// Nothing is written in dry run mode
+    pub fn save(&mut self, args: &Args, dirs: &InstallDirs) -> Result<()> {
+        if args.dry_run {
+            return Ok(());
+        }
+        self.tools.retain(|_, record| !record.is_empty());
+        let path = Manifest::get_path(dirs);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn is_installed(&self, tool_key: &str) -> bool {
+        self.tools
+            .get(tool_key)
+            .is_some_and(|record| !record.is_empty())
+    }
+}
@@ -1,10 +1,13 @@
-use crate::args::Args;
+use crate::args::{Args, ConflictPolicy};
+use crate::manifest::ManifestRecord;
+use crate::status::FileStatus;
+use crate::transaction::Transaction;
 use crate::utils::{
-    find_common_path, find_relative_path, get_bin_dir, get_config_dir, get_data_dir, make_absolute,
-    prompt,
+    find_common_path, find_relative_path, make_absolute, same_content, InstallDirs,
 };
 use crate::utils::{BLUE, GREEN, NC, RED, RESTORE, SAVE, YELLOW};
 use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::{fs, io};
@@ -16,11 +19,30 @@ pub enum PackageResult {
     Ignored,
     Installed,
     Linked,
+    UpToDate,
 }
 
 /// (bin, config)
 pub type PackageInfo = (PackageResult, PackageResult);
 
+/// Exact paths a package is installed to, `[tools.<key>.destination]` in the configuration
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct Destination {
+    pub bin: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    pub lib: Option<PathBuf>,
+}
+
+/// A file or directory put on disk when installing a package
+#[derive(Debug)]
+pub struct InstallTarget {
+    /// "bin", "lib" or "config"
+    pub field: &'static str,
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    pub linked: bool,
+}
+
 pub trait Package {
     fn name(&self) -> &String;
 
@@ -39,69 +61,269 @@ pub trait Package {
         })
     }
 
-    fn get_bin_path(&self) -> PathBuf {
-        get_bin_dir().join(self.name())
+    /// Destinations replacing the default ones
+    fn destination(&self) -> Option<&Destination> {
+        None
+    }
+
+    fn get_bin_path(&self, dirs: &InstallDirs) -> PathBuf {
+        self.destination()
+            .and_then(|d| d.bin.clone())
+            .unwrap_or_else(|| dirs.bin.join(self.name()))
+    }
+
+    fn get_config_path(&self, dirs: &InstallDirs) -> Option<PathBuf> {
+        self.get_config_name().map(|name| {
+            self.destination()
+                .and_then(|d| d.config.clone())
+                .unwrap_or_else(|| dirs.config.join(name))
+        })
+    }
+
+    fn get_lib_path(&self, dirs: &InstallDirs) -> Option<PathBuf> {
+        self.lib().map(|_| {
+            self.destination()
+                .and_then(|d| d.lib.clone())
+                .unwrap_or_else(|| dirs.data.join(self.name()))
+        })
     }
 
-    fn get_config_path(&self) -> Option<PathBuf> {
-        self.get_config_name()
-            .map(|name| get_config_dir().join(name))
+    /// Every file that `install` puts on disk, in installation order
+    fn get_install_targets(&self, args: &Args, dirs: &InstallDirs) -> Result<Vec<InstallTarget>> {
+        let mut targets = Vec::new();
+        match (self.lib(), self.get_lib_path(dirs)) {
+            (Some(lib), Some(path)) if !args.symbolic => {
+                let ancestor = find_common_path(self.bin(), lib)?;
+                let bin = path.join(find_relative_path(self.bin(), &ancestor)?);
+                targets.push(InstallTarget {
+                    field: "lib",
+                    source: ancestor,
+                    destination: path,
+                    linked: false,
+                });
+                targets.push(InstallTarget {
+                    field: "bin",
+                    source: bin,
+                    destination: self.get_bin_path(dirs),
+                    linked: true,
+                });
+            }
+            _ => targets.push(InstallTarget {
+                field: "bin",
+                source: make_absolute(self.bin())?,
+                destination: self.get_bin_path(dirs),
+                linked: args.symbolic,
+            }),
+        }
+        if let (Some(config), Some(path)) = (self.config(), self.get_config_path(dirs)) {
+            targets.push(InstallTarget {
+                field: "config",
+                source: make_absolute(config)?,
+                destination: path,
+                linked: args.symbolic,
+            });
+        }
+        Ok(targets)
     }
 
-    fn install(&self, args: &Args) -> Result<PackageInfo> {
-        Ok((self.install_bin(args)?, self.install_config(args)?))
+    fn install(&self, args: &Args, tx: &mut Transaction) -> Result<PackageInfo> {
+        Ok((self.install_bin(args, tx)?, self.install_config(args, tx)?))
     }
 
-    fn install_bin(&self, args: &Args) -> Result<PackageResult> {
+    fn install_bin(&self, args: &Args, tx: &mut Transaction) -> Result<PackageResult> {
+        let dirs = tx.dirs;
         if args.symbolic || self.lib().is_none() {
-            self.install_files(self.bin(), &self.get_bin_path(), args)
+            self.install_files(self.bin(), &dirs.staged(self.get_bin_path(dirs)), args, tx)
         } else {
             let lib = self.lib().unwrap();
             let ancestor = find_common_path(self.bin(), lib)?;
-            let path = get_data_dir().join(self.name());
-            self.install_files(&ancestor, &path, args)?;
-            // Activate symbolic in args to link bin to lib
+            let path = self.get_lib_path(dirs).unwrap();
+            self.install_files(&ancestor, &dirs.staged(&path), args, tx)?;
+            // Activate symbolic in args to link bin to lib, where it will finally be
             let mut sym_args = args.clone();
             sym_args.symbolic = true;
             let relative = find_relative_path(self.bin(), &ancestor)?;
             let bin = path.join(relative);
-            self.install_files(&bin, &self.get_bin_path(), &sym_args)
+            self.install_files(&bin, &dirs.staged(self.get_bin_path(dirs)), &sym_args, tx)
         }
     }
 
-    fn install_config(&self, args: &Args) -> Result<PackageResult> {
-        if let (Some(config), Some(path)) = (self.config(), &self.get_config_path()) {
-            self.install_files(config, path, args)
+    fn install_config(&self, args: &Args, tx: &mut Transaction) -> Result<PackageResult> {
+        if let (Some(config), Some(path)) = (self.config(), &self.get_config_path(tx.dirs)) {
+            self.install_files(config, &tx.dirs.staged(path), args, tx)
         } else {
             Ok(PackageResult::Ignored)
         }
     }
 
-    fn remove(&self, args: &Args) -> Result<PackageInfo> {
-        Ok((self.remove_bin(args)?, self.remove_config(args)?))
+    /// Bring what `record` says is installed up to date with the sources
+    fn update(
+        &self,
+        args: &Args,
+        record: &ManifestRecord,
+        tx: &mut Transaction,
+    ) -> Result<PackageInfo> {
+        Ok((
+            self.update_bin(args, record, tx)?,
+            self.update_config(args, record, tx)?,
+        ))
+    }
+
+    fn update_bin(
+        &self,
+        args: &Args,
+        record: &ManifestRecord,
+        tx: &mut Transaction,
+    ) -> Result<PackageResult> {
+        match self.is_bin_up_to_date(tx.dirs)? {
+            None => Ok(PackageResult::Ignored),
+            Some(true) => Ok(PackageResult::UpToDate),
+            Some(false) => {
+                // Sources changed, overwrite what is installed
+                let update_args = Self::get_update_args(args, record, false, tx.dirs)?;
+                self.install_bin(&update_args, tx)
+            }
+        }
     }
 
-    fn remove_bin(&self, args: &Args) -> Result<PackageResult> {
-        let path = self.get_bin_path();
+    fn update_config(
+        &self,
+        args: &Args,
+        record: &ManifestRecord,
+        tx: &mut Transaction,
+    ) -> Result<PackageResult> {
+        if let (Some(config), Some(path)) = (self.config(), &self.get_config_path(tx.dirs)) {
+            let path = &tx.dirs.staged(path);
+            match self.is_up_to_date(config, path)? {
+                None => Ok(PackageResult::Ignored),
+                Some(true) => Ok(PackageResult::UpToDate),
+                Some(false) => {
+                    let update_args = Self::get_update_args(args, record, true, tx.dirs)?;
+                    self.install_files(config, path, &update_args, tx)
+                }
+            }
+        } else {
+            Ok(PackageResult::Ignored)
+        }
+    }
+
+    /// Arguments replacing the installed files of the config side or of the bin side,
+    /// lib included. A copy still as the manifest recorded it is overwritten, one
+    /// modified since or not recorded is backed up
+    fn get_update_args(
+        args: &Args,
+        record: &ManifestRecord,
+        config: bool,
+        dirs: &InstallDirs,
+    ) -> Result<Args> {
+        let mut update_args = args.clone();
+        update_args.force = true;
+        if args.on_conflict.is_none() {
+            let mut entries = record
+                .files
+                .iter()
+                .filter(|entry| (entry.field == "config") == config)
+                .peekable();
+            let mut unmodified = entries.peek().is_some();
+            for entry in entries {
+                unmodified &= FileStatus::of_entry(entry, record, dirs)? != FileStatus::Modified;
+            }
+            if unmodified {
+                update_args.on_conflict = Some(ConflictPolicy::Overwrite);
+            }
+        }
+        Ok(update_args)
+    }
+
+    /// None if the binary is not installed
+    fn is_bin_up_to_date(&self, dirs: &InstallDirs) -> Result<Option<bool>> {
+        let path = dirs.staged(self.get_bin_path(dirs));
+        match self.lib() {
+            Some(lib)
+                if path.is_symlink() && fs::read_link(&path)? != make_absolute(self.bin())? =>
+            {
+                // bin is linked inside the installed copy of the library
+                let ancestor = find_common_path(self.bin(), lib)?;
+                let data = self.get_lib_path(dirs).unwrap();
+                let bin = data.join(find_relative_path(self.bin(), &ancestor)?);
+                Ok(Some(
+                    fs::read_link(&path)? == bin && same_content(&ancestor, dirs.staged(&data))?,
+                ))
+            }
+            _ => self.is_up_to_date(self.bin(), &path),
+        }
+    }
+
+    /// None if `to` does not exist, otherwise whether `to` still matches `from`
+    fn is_up_to_date<P: AsRef<Path>>(&self, from: P, to: P) -> Result<Option<bool>> {
+        let to = to.as_ref();
+        if to.is_symlink() {
+            Ok(Some(fs::read_link(to)? == make_absolute(&from)?))
+        } else if to.exists() {
+            Ok(Some(same_content(from, to)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Remove the files listed in the manifest record, keeping the ones the user refused to delete
+    fn remove_recorded(
+        &self,
+        record: &mut ManifestRecord,
+        args: &Args,
+        tx: &mut Transaction,
+    ) -> Result<PackageInfo> {
+        let (mut bin, mut config) = (PackageResult::Ignored, PackageResult::Ignored);
+        let mut kept = Vec::new();
+        // remove in reverse order so that links go before what they point to
+        for entry in record.files.iter().rev() {
+            let res = if args.force {
+                self.remove_files_unchecked(tx.dirs.staged(&entry.path), args, tx)?
+            } else {
+                self.remove_files(tx.dirs.staged(&entry.path), args, tx)?
+            };
+            if let PackageResult::Canceled = res {
+                kept.insert(0, entry.clone());
+            }
+            match entry.field.as_str() {
+                "bin" => bin = res,
+                "config" => config = res,
+                _ => (),
+            }
+        }
+        record.files = kept;
+        Ok((bin, config))
+    }
+
+    fn remove(&self, args: &Args, tx: &mut Transaction) -> Result<PackageInfo> {
+        Ok((self.remove_bin(args, tx)?, self.remove_config(args, tx)?))
+    }
+
+    fn remove_bin(&self, args: &Args, tx: &mut Transaction) -> Result<PackageResult> {
+        let path = tx.dirs.staged(self.get_bin_path(tx.dirs));
+        let lib_path = self.get_lib_path(tx.dirs).map(|path| tx.dirs.staged(path));
         if args.force {
-            if self.lib().is_some() {
-                self.remove_files_unchecked(get_data_dir().join(self.name()))?;
+            if let Some(lib_path) = lib_path {
+                self.remove_files_unchecked(lib_path, args, tx)?;
             }
-            self.remove_files_unchecked(&path)
+            self.remove_files_unchecked(&path, args, tx)
         } else {
-            if self.lib().is_some() {
-                self.remove_files(get_data_dir().join(self.name()))?;
+            if let Some(lib_path) = lib_path {
+                self.remove_files(lib_path, args, tx)?;
             }
-            self.remove_files(&path)
+            self.remove_files(&path, args, tx)
         }
     }
 
-    fn remove_config(&self, args: &Args) -> Result<PackageResult> {
-        if let Some(config) = self.get_config_path() {
+    fn remove_config(&self, args: &Args, tx: &mut Transaction) -> Result<PackageResult> {
+        if let Some(config) = self
+            .get_config_path(tx.dirs)
+            .map(|path| tx.dirs.staged(path))
+        {
             if args.force {
-                self.remove_files_unchecked(config)
+                self.remove_files_unchecked(config, args, tx)
             } else {
-                self.remove_files(config)
+                self.remove_files(config, args, tx)
             }
         } else {
             Ok(PackageResult::Ignored)
@@ -113,85 +335,152 @@ pub trait Package {
         from: P,
         to: P,
         args: &Args,
+        tx: &mut Transaction,
     ) -> Result<PackageResult> {
         let f_display = from.as_ref().display();
         let t_display = to.as_ref().display();
-        if args.symbolic {
-            std::os::unix::fs::symlink(make_absolute(&from)?, &to)?;
+        if args.dry_run {
+            if args.symbolic {
+                println!("{BLUE}PLAN{NC}: link {t_display} {BLUE}->{NC} {f_display}");
+                Ok(PackageResult::Linked)
+            } else {
+                println!("{BLUE}PLAN{NC}: copy {f_display} -> {t_display}");
+                Ok(PackageResult::Installed)
+            }
+        } else if args.symbolic {
+            let from = make_absolute(&from)?;
+            tx.create(&to, || Ok(std::os::unix::fs::symlink(&from, &to)?))?;
             println!("{BLUE}LINKED{NC}: {t_display} {BLUE}->{NC} {f_display}");
             Ok(PackageResult::Linked)
         } else {
             print!("{SAVE}INSTALLING: {f_display} -> {t_display}");
             io::stdout().flush()?;
 
-            if from.as_ref().is_dir() {
-                copy_dir::copy_dir(&from, &to)?;
-            } else {
-                fs::copy(&from, &to)?;
-            }
+            tx.create(&to, || {
+                if from.as_ref().is_dir() {
+                    copy_dir::copy_dir(&from, &to)?;
+                } else {
+                    fs::copy(&from, &to)?;
+                }
+                Ok(())
+            })?;
 
             println!("{RESTORE}{GREEN}INSTALLED{NC}: {f_display} -> {t_display}");
             Ok(PackageResult::Installed)
         }
     }
 
-    fn install_files<P: AsRef<Path>>(&self, from: P, to: P, args: &Args) -> Result<PackageResult> {
-        if args.force {
-            self.remove_files_unchecked(&to)?;
-            self.install_files_unchecked(&from, &to, args)
-        } else {
-            let path = to.as_ref();
-            if path.exists() || path.is_symlink() {
-                let display = path.display();
-
-                match prompt(&format!(
-                    "{SAVE}{YELLOW}WARNING{NC}: Do you want to overwrite '{display}' (y/N): ",
-                ))?
-                .as_str()
-                {
-                    "y" => {
-                        self.remove_files_unchecked(&to)?;
-                        self.install_files_unchecked(from, to, args)
-                    }
-                    _ => Ok(PackageResult::Canceled),
+    fn install_files<P: AsRef<Path>>(
+        &self,
+        from: P,
+        to: P,
+        args: &Args,
+        tx: &mut Transaction,
+    ) -> Result<PackageResult> {
+        let path = to.as_ref();
+        // a destination may be outside of the install directories created beforehand
+        if let Some(parent) = path.parent().filter(|_| !args.dry_run) {
+            fs::create_dir_all(parent)?;
+        }
+        if !path.exists() && !path.is_symlink() {
+            return self.install_files_unchecked(from, to, args, tx);
+        }
+        let display = path.display();
+        let policy = match args.on_conflict {
+            Some(policy) => policy,
+            None if args.force => ConflictPolicy::Backup,
+            None if args.dry_run => {
+                println!("{BLUE}PLAN{NC}: ask to overwrite '{display}'");
+                ConflictPolicy::Backup
+            }
+            None => {
+                if args.confirm(
+                    &format!(
+                        "{SAVE}{YELLOW}WARNING{NC}: Do you want to overwrite '{display}' (y/N): "
+                    ),
+                    false,
+                )? {
+                    ConflictPolicy::Backup
+                } else {
+                    ConflictPolicy::Skip
                 }
-            } else {
-                self.install_files_unchecked(from, to, args)
             }
+        };
+        match policy {
+            ConflictPolicy::Overwrite => {
+                self.remove_files_unchecked(&to, args, tx)?;
+                self.install_files_unchecked(from, to, args, tx)
+            }
+            ConflictPolicy::Backup => {
+                self.backup_files(&to, args, tx)?;
+                self.install_files_unchecked(from, to, args, tx)
+            }
+            ConflictPolicy::Skip => {
+                println!("{YELLOW}WARNING{NC}: Skipped '{display}'");
+                Ok(PackageResult::Canceled)
+            }
+            ConflictPolicy::Fail => Err(anyhow!("'{display}' already exists")),
         }
     }
 
-    fn remove_files<P: AsRef<Path>>(&self, path: P) -> Result<PackageResult> {
+    fn remove_files<P: AsRef<Path>>(
+        &self,
+        path: P,
+        args: &Args,
+        tx: &mut Transaction,
+    ) -> Result<PackageResult> {
         let path = path.as_ref();
-        if path.exists() || path.is_symlink() {
-            let display = path.display();
-            match prompt(&format!(
-                "{SAVE}{YELLOW}WARNING{NC}: Do you want to remove '{display}' (Y/n): "
-            ))?
-            .trim()
-            {
-                "n" => {
-                    println!("{YELLOW}WARNING{NC}: Canceled '{display}' deletion");
-                    Ok(PackageResult::Canceled)
+        if !path.exists() && !path.is_symlink() {
+            return Ok(PackageResult::Ignored);
+        }
+        let display = path.display();
+        let policy = match args.on_conflict {
+            Some(policy) => policy,
+            None if args.dry_run => {
+                println!("{BLUE}PLAN{NC}: ask to remove '{display}'");
+                ConflictPolicy::Overwrite
+            }
+            None => {
+                if args.confirm(
+                    &format!(
+                        "{SAVE}{YELLOW}WARNING{NC}: Do you want to remove '{display}' (Y/n): "
+                    ),
+                    true,
+                )? {
+                    ConflictPolicy::Overwrite
+                } else {
+                    ConflictPolicy::Skip
                 }
-                _ => self.remove_files_unchecked(path),
             }
-        } else {
-            Ok(PackageResult::Ignored)
+        };
+        match policy {
+            ConflictPolicy::Overwrite => self.remove_files_unchecked(path, args, tx),
+            ConflictPolicy::Backup => self.backup_files(path, args, tx),
+            ConflictPolicy::Skip => {
+                println!("{YELLOW}WARNING{NC}: Canceled '{display}' deletion");
+                Ok(PackageResult::Canceled)
+            }
+            ConflictPolicy::Fail => Err(anyhow!("'{display}' would be removed")),
         }
     }
 
-    fn remove_files_unchecked<P: AsRef<Path>>(&self, path: P) -> Result<PackageResult> {
+    fn remove_files_unchecked<P: AsRef<Path>>(
+        &self,
+        path: P,
+        args: &Args,
+        tx: &mut Transaction,
+    ) -> Result<PackageResult> {
         if path.as_ref().exists() || path.as_ref().is_symlink() {
             let display = path.as_ref().display();
+            if args.dry_run {
+                println!("{BLUE}PLAN{NC}: delete {display}");
+                return Ok(PackageResult::Removed);
+            }
             print!("{SAVE}{RED}DELETING{NC}: {display}");
             io::stdout().flush()?;
 
-            if path.as_ref().is_dir() {
-                std::fs::remove_dir_all(&path)?;
-            } else {
-                std::fs::remove_file(&path)?;
-            }
+            // kept aside until the transaction is committed
+            tx.move_aside(&path)?;
 
             println!("{RESTORE}{RED}DELETED{NC}: {display}");
             return Ok(PackageResult::Removed);
@@ -199,6 +488,27 @@ pub trait Package {
         Ok(PackageResult::Ignored)
     }
 
+    /// Move an existing target into the backups before it gets replaced
+    fn backup_files<P: AsRef<Path>>(
+        &self,
+        path: P,
+        args: &Args,
+        tx: &mut Transaction,
+    ) -> Result<PackageResult> {
+        let path = path.as_ref();
+        if path.exists() || path.is_symlink() {
+            let display = path.display();
+            if args.dry_run {
+                println!("{BLUE}PLAN{NC}: back up {display}");
+            } else {
+                tx.back_up(path)?;
+                println!("{YELLOW}BACKED UP{NC}: {display}");
+            }
+            return Ok(PackageResult::Removed);
+        }
+        Ok(PackageResult::Ignored)
+    }
+
     fn validate(&self) -> Result<()> {
         // TODO handle requires
         if !self.bin().exists() {
@@ -0,0 +1,141 @@
+use crate::{config::Config, dependencies::Dependencies, editor::Editor, group::Group, tool::Tool};
+use serde::Serialize;
+use std::{collections::BTreeMap, path::PathBuf};
+
+/// Structured version of the list action
+#[derive(Serialize, Debug)]
+pub struct ListReport<'l> {
+    pub editor: EditorReport<'l>,
+    pub tools: BTreeMap<&'l String, ToolReport<'l>>,
+    pub groups: BTreeMap<&'l String, GroupReport<'l>>,
+    pub default_groups: Option<&'l Vec<String>>,
+    pub default_tools: Option<&'l Vec<String>>,
+    pub capabilities: BTreeMap<&'l String, CapabilityReport<'l>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct EditorReport<'l> {
+    #[serde(flatten)]
+    pub editor: &'l Editor,
+    pub installed: bool,
+    /// "bin", "lib" or "config" fields whose path does not exist
+    pub missing_paths: Vec<&'static str>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ToolReport<'l> {
+    #[serde(flatten)]
+    pub tool: &'l Tool,
+    pub installed: bool,
+    pub valid: bool,
+    pub invalid_paths: BTreeMap<String, &'l PathBuf>,
+    /// dependency key -> error
+    pub dependency_errors: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct GroupReport<'l> {
+    #[serde(flatten)]
+    pub group: &'l Group,
+    pub valid: bool,
+    /// Tools of the group, included groups expanded
+    pub expanded_tools: Vec<&'l String>,
+    pub unsatisfied_tools: Vec<&'l str>,
+    pub cycle: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CapabilityReport<'l> {
+    pub providers: Vec<&'l String>,
+    /// Tool satisfying the capability
+    pub selected: Option<&'l String>,
+}
+
+impl<'l> ListReport<'l> {
+    pub fn new(config: &'l Config, dependencies: &'l Dependencies<'l>) -> ListReport<'l> {
+        let editor = &config.editor;
+        let missing_paths = [
+            ("bin", Some(&editor.bin)),
+            ("config", Some(&editor.config)),
+            ("lib", editor.lib.as_ref()),
+        ]
+        .into_iter()
+        .filter(|(_, path)| path.is_some_and(|p| !p.exists()))
+        .map(|(field, _)| field)
+        .collect();
+
+        let tools = config
+            .tools
+            .iter()
+            .flatten()
+            .map(|(key, tool)| {
+                let unsatisfied = dependencies.unsatisfied_tools.get(key);
+                let report = ToolReport {
+                    tool,
+                    installed: dependencies.manifest.is_installed(key),
+                    valid: unsatisfied.is_none(),
+                    invalid_paths: unsatisfied
+                        .and_then(|u| u.paths.as_ref())
+                        .map(|paths| paths.iter().map(|(k, v)| (k.to_owned(), *v)).collect())
+                        .unwrap_or_default(),
+                    dependency_errors: unsatisfied
+                        .and_then(|u| u.required.as_ref())
+                        .map(|required| {
+                            required
+                                .iter()
+                                .map(|(k, (_, error))| (k.to_string(), error.to_string()))
+                                .collect()
+                        })
+                        .unwrap_or_default(),
+                };
+                (key, report)
+            })
+            .collect();
+
+        let groups = config
+            .groups
+            .iter()
+            .flatten()
+            .map(|(key, group)| {
+                let unsatisfied = dependencies.unsatisfied_groups.get(key);
+                let expanded =
+                    Group::expand(key, config.groups.as_ref().unwrap(), config.tools.as_ref());
+                let report = GroupReport {
+                    group,
+                    valid: unsatisfied.is_none(),
+                    expanded_tools: expanded.tools,
+                    unsatisfied_tools: unsatisfied
+                        .map(|u| u.unsatisfied_tools.clone())
+                        .unwrap_or_default(),
+                    cycle: expanded.cycle,
+                };
+                (key, report)
+            })
+            .collect();
+
+        let capabilities = config
+            .get_capabilities()
+            .into_iter()
+            .map(|(capability, providers)| {
+                let report = CapabilityReport {
+                    providers,
+                    selected: config.get_selected_provider(capability, dependencies),
+                };
+                (capability, report)
+            })
+            .collect();
+
+        ListReport {
+            editor: EditorReport {
+                editor,
+                installed: !dependencies.manifest.editor.is_empty(),
+                missing_paths,
+            },
+            tools,
+            groups,
+            default_groups: config.default_groups.as_ref(),
+            default_tools: config.default_tools.as_ref(),
+            capabilities,
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use crate::args::Shell;
+use crate::utils::{get_home_dir, InstallDirs, NC, YELLOW};
+use anyhow::Result;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Lines surrounding what is added to the shell configuration, `#` starts a comment in
+/// every supported shell
+const BLOCK_START: &str = "# >>> editor env >>>";
+const BLOCK_END: &str = "# <<< editor env <<<";
+/// Markers of the block written by earlier versions, still recognized
+const OLD_BLOCK: (&str, &str) = ("# >>> editor PATH >>>", "# <<< editor PATH <<<");
+
+/// `line` without its line ending
+fn trim_line(line: &str) -> &str {
+    line.trim_end_matches(['\n', '\r'])
+}
+
+/// `content` with the block, current or of earlier versions, replaced by `by`, None
+/// unless both of its markers are there. What is outside of the block is kept byte for
+/// byte
+fn replace_block(content: &str, by: &str) -> Option<String> {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let (start, end_marker) = lines.iter().enumerate().find_map(|(index, line)| {
+        [(BLOCK_START, BLOCK_END), OLD_BLOCK]
+            .into_iter()
+            .find(|(start, _)| trim_line(line) == *start)
+            .map(|(_, end)| (index, end))
+    })?;
+    let end = start
+        + lines[start..]
+            .iter()
+            .position(|line| trim_line(line) == end_marker)?;
+    Some(lines[..start].concat() + by + &lines[end + 1..].concat())
+}
+
+pub const ENV_DIRNAME: &str = "editor-env";
+
+/// One script per syntax, bash and zsh read the POSIX one
+const SCRIPT_SHELLS: [Shell; 3] = [Shell::Posix, Shell::Fish, Shell::Nu];
+
+impl Shell {
+    /// Shell of the user from $SHELL, POSIX when unknown
+    pub fn detect() -> Shell {
+        let shell = std::env::var("SHELL").unwrap_or_default();
+        match Path::new(&shell).file_name().and_then(|name| name.to_str()) {
+            Some("bash") => Shell::Bash,
+            Some("zsh") => Shell::Zsh,
+            Some("fish") => Shell::Fish,
+            Some("nu") => Shell::Nu,
+            _ => Shell::Posix,
+        }
+    }
+
+    /// File read by the shell when it starts
+    pub fn get_config_path(&self) -> Result<PathBuf> {
+        let home = get_home_dir()?;
+        let config_home =
+            std::env::var_os("XDG_CONFIG_HOME").map_or_else(|| home.join(".config"), PathBuf::from);
+        Ok(match self {
+            Shell::Bash => home.join(".bashrc"),
+            Shell::Zsh => std::env::var_os("ZDOTDIR")
+                .map_or(home, PathBuf::from)
+                .join(".zshrc"),
+            Shell::Fish => config_home.join("fish").join("config.fish"),
+            Shell::Nu => config_home.join("nushell").join("env.nu"),
+            Shell::Posix => home.join(".profile"),
+        })
+    }
+
+    /// Directory of the generated env scripts
+    pub fn get_env_dir(dirs: &InstallDirs) -> PathBuf {
+        dirs.state.join(ENV_DIRNAME)
+    }
+
+    /// Generated script setting PATH and the variables of the installed tools
+    pub fn get_env_path(&self, dirs: &InstallDirs) -> PathBuf {
+        Shell::get_env_dir(dirs).join(match self {
+            Shell::Bash | Shell::Zsh | Shell::Posix => "env.sh",
+            Shell::Fish => "env.fish",
+            Shell::Nu => "env.nu",
+        })
+    }
+
+    /// The line to put in the shell configuration to load the env script
+    pub fn get_source_line(&self, dirs: &InstallDirs) -> String {
+        let path = self.get_env_path(dirs);
+        let path = path.display();
+        match self {
+            Shell::Bash | Shell::Zsh | Shell::Posix => format!(". \"{path}\""),
+            Shell::Fish | Shell::Nu => format!("source \"{path}\""),
+        }
+    }
+
+    /// Content of the env script, values are written between double quotes so that POSIX
+    /// shells and fish expand the variables they contain, nu keeps them literal
+    fn get_env_script(&self, dirs: &InstallDirs, vars: &[(String, String)]) -> String {
+        let dir = dirs.bin.display();
+        let mut lines = vec![String::from(
+            "# Generated by editor, changes are overwritten",
+        )];
+        lines.push(match self {
+            Shell::Bash | Shell::Zsh | Shell::Posix => {
+                format!("case \":$PATH:\" in\n    *\":{dir}:\"*) ;;\n    *) export PATH=\"{dir}:$PATH\" ;;\nesac")
+            }
+            Shell::Fish => format!("contains \"{dir}\" $PATH; or set -gx PATH \"{dir}\" $PATH"),
+            Shell::Nu => format!(
+                "$env.PATH = ($env.PATH | split row (char esep) | prepend \"{dir}\" | uniq)"
+            ),
+        });
+        for (key, value) in vars {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            lines.push(match self {
+                Shell::Bash | Shell::Zsh | Shell::Posix => format!("export {key}=\"{value}\""),
+                Shell::Fish => format!("set -gx {key} \"{value}\""),
+                Shell::Nu => format!("$env.{key} = \"{value}\""),
+            });
+        }
+        lines.join("\n") + "\n"
+    }
+
+    /// Write the env script of every shell
+    pub fn write_env_scripts(dirs: &InstallDirs, vars: &[(String, String)]) -> Result<()> {
+        std::fs::create_dir_all(dirs.staged(Shell::get_env_dir(dirs)))?;
+        for shell in SCRIPT_SHELLS {
+            std::fs::write(
+                dirs.staged(shell.get_env_path(dirs)),
+                shell.get_env_script(dirs, vars),
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn remove_env_scripts(dirs: &InstallDirs) -> Result<()> {
+        let dir = dirs.staged(Shell::get_env_dir(dirs));
+        if dir.exists() {
+            std::fs::remove_dir_all(dir)?;
+        }
+        Ok(())
+    }
+
+    fn read_config(&self) -> Result<String> {
+        let path = self.get_config_path()?;
+        if path.exists() {
+            Ok(std::fs::read_to_string(path)?)
+        } else {
+            Ok(String::new())
+        }
+    }
+
+    /// Whether the block has already been added to the shell configuration
+    pub fn has_block(&self) -> Result<bool> {
+        Ok(self.read_config()?.lines().any(|line| line == BLOCK_START))
+    }
+
+    fn warn_unterminated_block(path: &Path) {
+        println!(
+            "{YELLOW}WARNING{NC}: The editor block of {} has no end marker, remove it by hand",
+            path.display()
+        );
+    }
+
+    /// Add the block sourcing the env script to the shell configuration, false if it was
+    /// already there. The PATH block and the bare PATH export of earlier versions are
+    /// replaced by the block
+    pub fn add_block(&self, dirs: &InstallDirs) -> Result<bool> {
+        if self.has_block()? {
+            return Ok(false);
+        }
+        let path = self.get_config_path()?;
+        let content = self.read_config()?;
+        let block = format!("{BLOCK_START}\n{}\n{BLOCK_END}", self.get_source_line(dirs));
+        if content.lines().any(|line| line == OLD_BLOCK.0) {
+            return match replace_block(&content, &format!("{block}\n")) {
+                Some(migrated) => {
+                    std::fs::write(path, migrated)?;
+                    Ok(true)
+                }
+                None => {
+                    Shell::warn_unterminated_block(&path);
+                    Ok(false)
+                }
+            };
+        }
+        let legacy = format!("export PATH=\"{}:$PATH\"", dirs.bin.display());
+        if content.lines().any(|line| line == legacy) {
+            let migrated: String = content
+                .split_inclusive('\n')
+                .map(|line| {
+                    if trim_line(line) == legacy {
+                        line.replacen(&legacy, &block, 1)
+                    } else {
+                        line.to_string()
+                    }
+                })
+                .collect();
+            std::fs::write(path, migrated)?;
+            return Ok(true);
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut config = std::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let separator = if content.is_empty() || content.ends_with('\n') {
+            ""
+        } else {
+            "\n"
+        };
+        config.write_all(format!("{separator}{block}\n").as_bytes())?;
+        Ok(true)
+    }
+
+    /// Take the block out of the shell configuration, false if it was not there. A block
+    /// without its end marker is left untouched rather than guessing where it stops
+    pub fn remove_block(&self) -> Result<bool> {
+        let content = self.read_config()?;
+        if !content
+            .lines()
+            .any(|line| line == BLOCK_START || line == OLD_BLOCK.0)
+        {
+            return Ok(false);
+        }
+        let path = self.get_config_path()?;
+        match replace_block(&content, "") {
+            Some(content) => {
+                std::fs::write(path, content)?;
+                Ok(true)
+            }
+            None => {
+                Shell::warn_unterminated_block(&path);
+                Ok(false)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replace_block_keeps_what_surrounds_the_block() {
+        let content = format!("before\r\n{BLOCK_START}\n. \"env.sh\"\n{BLOCK_END}\nafter");
+        assert_eq!(replace_block(&content, "").unwrap(), "before\r\nafter");
+        let content = format!("{BLOCK_START}\r\nsource env\r\n{BLOCK_END}\r\n");
+        assert_eq!(replace_block(&content, "").unwrap(), "");
+    }
+
+    #[test]
+    fn replace_block_finds_blocks_of_earlier_versions() {
+        let (start, end) = OLD_BLOCK;
+        let content = format!("before\n{start}\nexport PATH\n{end}\n");
+        assert_eq!(replace_block(&content, "").unwrap(), "before\n");
+        // migrated to the current block at the same place
+        let content = format!("before\n{start}\nexport PATH\n{end}\nafter\n");
+        let block = format!("{BLOCK_START}\n. \"env.sh\"\n{BLOCK_END}\n");
+        assert_eq!(
+            replace_block(&content, &block).unwrap(),
+            format!("before\n{block}after\n")
+        );
+        // the end marker has to match the start one
+        let content = format!("{start}\nexport PATH\n{BLOCK_END}\n");
+        assert_eq!(replace_block(&content, ""), None);
+    }
+
+    #[test]
+    fn replace_block_needs_both_markers() {
+        assert_eq!(replace_block("alias ll='ls -l'\n", ""), None);
+        let content = format!("{BLOCK_START}\n. \"env.sh\"\nalias ll='ls -l'\n");
+        assert_eq!(replace_block(&content, ""), None);
+        let content = format!("{BLOCK_END}\n{BLOCK_START}\n");
+        assert_eq!(replace_block(&content, ""), None);
+    }
+
+    #[test]
+    fn env_scripts_set_path_and_quote_values() {
+        let dirs = InstallDirs {
+            bin: PathBuf::from("/home/user/.local/bin"),
+            ..Default::default()
+        };
+        let vars = [
+            (String::from("FIRST_HOME"), String::from("$HOME/.first")),
+            (String::from("QUOTED"), String::from("say \"hi\" \\o/")),
+        ];
+        let posix = Shell::Posix.get_env_script(&dirs, &vars);
+        assert!(posix.contains("*) export PATH=\"/home/user/.local/bin:$PATH\" ;;"));
+        assert!(posix.contains("\nexport FIRST_HOME=\"$HOME/.first\"\n"));
+        assert!(posix.contains("\nexport QUOTED=\"say \\\"hi\\\" \\\\o/\"\n"));
+        assert_eq!(Shell::Bash.get_env_script(&dirs, &vars), posix);
+
+        let fish = Shell::Fish.get_env_script(&dirs, &vars);
+        assert!(fish.contains("set -gx PATH \"/home/user/.local/bin\" $PATH"));
+        assert!(fish.contains("\nset -gx FIRST_HOME \"$HOME/.first\"\n"));
+
+        let nu = Shell::Nu.get_env_script(&dirs, &vars);
+        assert!(nu.contains("prepend \"/home/user/.local/bin\""));
+        assert!(nu.contains("\n$env.FIRST_HOME = \"$HOME/.first\"\n"));
+    }
+}
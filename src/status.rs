@@ -0,0 +1,121 @@
+use crate::args::Args;
+use crate::manifest::{InstallMethod, ManifestEntry, ManifestRecord};
+use crate::package::Package;
+use crate::utils::{hash_path, InstallDirs, CYAN, GREEN, NC, RED, YELLOW};
+use anyhow::Result;
+use std::fmt::Display;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileStatus {
+    Missing,
+    UpToDate,
+    /// Source changed since installation
+    Outdated,
+    /// Installed file changed since installation
+    Modified,
+    /// Link pointing to something else than the recorded source
+    StaleLink,
+    /// Present but not installed by this tool
+    Unmanaged,
+}
+
+impl Display for FileStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "{}",
+            match self {
+                FileStatus::Missing => format!("{RED}MISSING{NC}"),
+                FileStatus::UpToDate => format!("{GREEN}UP TO DATE{NC}"),
+                FileStatus::Outdated => format!("{YELLOW}OUTDATED{NC}"),
+                FileStatus::Modified => format!("{YELLOW}MODIFIED{NC}"),
+                FileStatus::StaleLink => format!("{RED}STALE LINK{NC}"),
+                FileStatus::Unmanaged => format!("{CYAN}UNMANAGED{NC}"),
+            }
+        ))
+    }
+}
+
+impl FileStatus {
+    /// Compare what is on disk with what the manifest recorded in `record`
+    pub fn of_entry(
+        entry: &ManifestEntry,
+        record: &ManifestRecord,
+        dirs: &InstallDirs,
+    ) -> Result<FileStatus> {
+        let path = &dirs.staged(&entry.path);
+        // a bin linked into the lib of the package points to the installed lib
+        let source = match record.get("lib") {
+            Some(lib) if entry.source.starts_with(&lib.path) => dirs.staged(&entry.source),
+            _ => entry.source.to_owned(),
+        };
+        if !path.exists() && !path.is_symlink() {
+            return Ok(FileStatus::Missing);
+        }
+        match (entry.method, path.is_symlink()) {
+            (InstallMethod::Linked, true) => {
+                if std::fs::read_link(path)? != entry.source || !source.exists() {
+                    Ok(FileStatus::StaleLink)
+                } else {
+                    Ok(FileStatus::UpToDate)
+                }
+            }
+            (InstallMethod::Copied, false) => {
+                if hash_path(path)? != entry.hash {
+                    Ok(FileStatus::Modified)
+                } else if !source.exists() || hash_path(&source)? != entry.hash {
+                    Ok(FileStatus::Outdated)
+                } else {
+                    Ok(FileStatus::UpToDate)
+                }
+            }
+            // a link replaced by a file or the other way around
+            _ => Ok(FileStatus::Modified),
+        }
+    }
+
+    /// Status of every destination of a package, (field, destination, status)
+    pub fn of_package<P: Package>(
+        package: &P,
+        record: Option<&ManifestRecord>,
+        args: &Args,
+        dirs: &InstallDirs,
+    ) -> Result<Vec<(String, PathBuf, FileStatus)>> {
+        match record.filter(|record| !record.is_empty()) {
+            Some(record) => record
+                .files
+                .iter()
+                .map(|entry| {
+                    Ok((
+                        entry.field.to_owned(),
+                        entry.path.to_owned(),
+                        FileStatus::of_entry(entry, record, dirs)?,
+                    ))
+                })
+                .collect(),
+            None => Ok(package
+                .get_install_targets(args, dirs)?
+                .into_iter()
+                .map(|target| {
+                    let path = dirs.staged(&target.destination);
+                    let status = if path.exists() || path.is_symlink() {
+                        FileStatus::Unmanaged
+                    } else {
+                        FileStatus::Missing
+                    };
+                    (target.field.to_string(), target.destination, status)
+                })
+                .collect()),
+        }
+    }
+
+    pub(crate) fn print(label: &str, files: &[(String, PathBuf, FileStatus)]) -> Result<()> {
+        println!("{label}");
+        for (field, path, status) in files {
+            println!("\t{field} : {} {status}", path.display());
+        }
+        std::io::stdout().flush()?;
+        Ok(())
+    }
+}
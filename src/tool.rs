@@ -1,24 +1,40 @@
 use crate::{
     args::Args,
     dependencies::Dependencies,
-    package::Package,
-    utils::{CYAN, GREEN, NC, RED},
+    package::{Destination, Package},
+    utils::{BLUE, CYAN, GREEN, NC, RED, YELLOW},
 };
 use anyhow::Result;
 use phf::{phf_map, Map};
-use serde::Deserialize;
-use std::{collections::HashMap, io::Write, path::PathBuf};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Write,
+    path::PathBuf,
+};
 
 const TOOL_FIELD_STR: Map<&str, &str> =
     phf_map! {"bin" => "Binary Source", "lib" => "Library Source", "config" => "Configuration"};
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct Tool {
     pub name: String,
     pub bin: PathBuf,
     pub config: Option<PathBuf>,
     pub lib: Option<PathBuf>,
     pub dependencies: Option<Vec<String>>,
+    /// Installed along when available, the tool works without them
+    pub optional_dependencies: Option<Vec<String>>,
+    /// Only one of these tools is required, an installed one is preferred
+    pub one_of: Option<Vec<String>>,
+    /// Capabilities other tools and groups can depend on instead of this tool key
+    pub provides: Option<Vec<String>>,
+    /// Tools that cannot be selected along with this one, e.g. installing the same binary
+    pub conflicts: Option<Vec<String>>,
+    /// Where to install instead of the bin, config and data directories
+    pub destination: Option<Destination>,
+    /// Environment variables set by the generated env scripts once installed
+    pub env: Option<BTreeMap<String, String>>,
 }
 impl Tool {
     pub(crate) fn get_invalid_paths(&self) -> Result<HashMap<String, &PathBuf>> {
@@ -59,9 +75,15 @@ impl Tool {
         } else {
             String::new()
         };
+        let installed = dependencies.manifest.tools.get(tool_key.as_str());
+        let installed_tool = if installed.is_some_and(|record| !record.is_empty()) {
+            format!("{BLUE}INSTALLED{NC} ")
+        } else {
+            String::new()
+        };
         if args.verbose {
             print!(
-                "Tool: {GREEN}{}{NC} (lsp: {GREEN}{}{NC}) {error_tool}\n",
+                "Tool: {GREEN}{}{NC} (lsp: {GREEN}{}{NC}) {installed_tool}{error_tool}\n",
                 tool_key, self.name,
             );
 
@@ -79,6 +101,16 @@ impl Tool {
                 }
             }
 
+            if let Some(record) = installed {
+                for entry in &record.files {
+                    println!(
+                        "\tInstalled : {} ({:?})",
+                        entry.path.display(),
+                        entry.method
+                    );
+                }
+            }
+
             if let Some(deps) = self.dependencies.as_ref() {
                 print!("\tDependencies :\n");
                 for dep in deps {
@@ -91,10 +123,47 @@ impl Tool {
                     );
                 }
             }
+            if let Some(conflicts) = self.conflicts.as_ref() {
+                println!("\tConflicts :");
+                for other in conflicts {
+                    let selected = if dependencies.in_conflict(tool_key, other) {
+                        format!("{YELLOW}SELECTED TOGETHER{NC}")
+                    } else {
+                        String::new()
+                    };
+                    println!("\t - {other} {selected}");
+                }
+            }
+            if let Some(env) = self.env.as_ref() {
+                println!("\tEnvironment :");
+                for (name, value) in env {
+                    println!("\t - {name}={value}");
+                }
+            }
+            if let Some(provides) = self.provides.as_ref() {
+                println!("\tProvides : {}", provides.join(", "));
+            }
+            if let Some(deps) = self.optional_dependencies.as_ref() {
+                println!("\tOptional dependencies :");
+                for dep in deps {
+                    println!("\t - {} {}", dep, dependencies.get_availability(dep));
+                }
+            }
+            if let Some(deps) = self.one_of.as_ref() {
+                println!(
+                    "\tOne of : {RED}{}{NC}",
+                    deps.first()
+                        .and_then(|dep| dependencies.get_error_dependencies(tool_key, dep))
+                        .unwrap_or_default()
+                );
+                for dep in deps {
+                    println!("\t - {} {}", dep, dependencies.get_availability(dep));
+                }
+            }
             print!("\n");
         } else {
             print!(
-                " - {GREEN}{tool_key}{NC} (lsp: {GREEN}{}{NC}) {error_tool}\n",
+                " - {GREEN}{tool_key}{NC} (lsp: {GREEN}{}{NC}) {installed_tool}{error_tool}\n",
                 self.name,
             );
         }
@@ -119,4 +188,8 @@ impl Package for Tool {
     fn lib(&self) -> Option<&PathBuf> {
         self.lib.as_ref()
     }
+
+    fn destination(&self) -> Option<&Destination> {
+        self.destination.as_ref()
+    }
 }
@@ -0,0 +1,293 @@
+use crate::backup::Backup;
+use crate::utils::{move_path, InstallDirs, NC, RED, YELLOW};
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+enum Step {
+    /// A file or directory put on disk
+    Created(PathBuf),
+    /// An existing file or directory moved into the staging directory,
+    /// kept as a backup of `backup` key on commit
+    MovedAside {
+        original: PathBuf,
+        staged: PathBuf,
+        backup: Option<String>,
+    },
+}
+
+/// Filesystem modifications of a run, undone on failure
+#[derive(Debug)]
+pub struct Transaction<'l> {
+    /// Where packages are installed, the staging directory and backups are in its state
+    pub dirs: &'l InstallDirs,
+    steps: Vec<Step>,
+    /// Tool key of the package being modified
+    key: String,
+}
+
+impl<'l> Transaction<'l> {
+    /// Run `action`, undoing everything it did on disk if it fails
+    pub fn run<T, F>(dirs: &'l InstallDirs, action: F) -> Result<T>
+    where
+        F: FnOnce(&mut Transaction<'l>) -> Result<T>,
+    {
+        let mut transaction = Transaction {
+            dirs,
+            steps: Vec::new(),
+            key: String::new(),
+        };
+        match action(&mut transaction) {
+            Ok(res) => {
+                transaction.commit()?;
+                Ok(res)
+            }
+            Err(e) => match transaction.rollback() {
+                Ok(()) => Err(e),
+                // the cause of the failure matters more than what could not be undone
+                Err(rollback) => Err(e.context(rollback)),
+            },
+        }
+    }
+
+    fn get_staging_dir(&self) -> PathBuf {
+        self.dirs.staged(
+            self.dirs
+                .state
+                .join(format!(".editor-transaction-{}", std::process::id())),
+        )
+    }
+
+    pub fn set_key(&mut self, key: &str) {
+        self.key = key.to_string();
+    }
+
+    /// Put `path` on disk with `create` and register it to be removed on rollback. What
+    /// a failing `create` left behind is registered too, but never a path that was
+    /// already there
+    pub fn create<P, F>(&mut self, path: P, create: F) -> Result<()>
+    where
+        P: AsRef<Path>,
+        F: FnOnce() -> Result<()>,
+    {
+        let path = path.as_ref();
+        let existed = path.exists() || path.is_symlink();
+        let res = create();
+        if res.is_ok() || (!existed && (path.exists() || path.is_symlink())) {
+            self.steps.push(Step::Created(path.to_path_buf()));
+        }
+        res
+    }
+
+    /// Move `path` away instead of deleting it, so that it can be put back
+    pub fn move_aside<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.stage(path.as_ref(), None)
+    }
+
+    /// Like `move_aside`, but the file is kept in the backups of the current key
+    pub fn back_up<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.stage(path.as_ref(), Some(self.key.to_owned()))
+    }
+
+    fn stage(&mut self, path: &Path, backup: Option<String>) -> Result<()> {
+        let original = path.to_path_buf();
+        let staged = self.get_staging_dir().join(self.steps.len().to_string());
+        std::fs::create_dir_all(&staged)?;
+        let staged = staged.join(original.file_name().unwrap_or(original.as_os_str()));
+        move_path(&original, &staged)?;
+        self.steps.push(Step::MovedAside {
+            original,
+            staged,
+            backup,
+        });
+        Ok(())
+    }
+
+    /// Keep every modification, save backups and drop what has been moved aside
+    pub fn commit(self) -> Result<()> {
+        let staging = self.get_staging_dir();
+        let mut backups: Vec<(String, Vec<(PathBuf, PathBuf)>)> = Vec::new();
+        for step in self.steps {
+            if let Step::MovedAside {
+                original,
+                staged,
+                backup: Some(key),
+            } = step
+            {
+                match backups.iter_mut().find(|(k, _)| *k == key) {
+                    Some((_, files)) => files.push((original, staged)),
+                    None => backups.push((key, vec![(original, staged)])),
+                }
+            }
+        }
+        for (key, files) in backups {
+            Backup::create(self.dirs, &key, files)?;
+        }
+
+        if staging.exists() {
+            std::fs::remove_dir_all(staging)?;
+        }
+        Ok(())
+    }
+
+    /// Undo every step, latest first, going on when one of them fails
+    pub fn rollback(self) -> Result<()> {
+        let staging = self.get_staging_dir();
+        if !self.steps.is_empty() {
+            println!("{YELLOW}WARNING{NC}: Rolling back previous modifications");
+        }
+        let mut failures = Vec::new();
+        for step in self.steps.into_iter().rev() {
+            match step {
+                Step::Created(path) => {
+                    let res = if path.is_dir() && !path.is_symlink() {
+                        std::fs::remove_dir_all(&path)
+                    } else if path.exists() || path.is_symlink() {
+                        std::fs::remove_file(&path)
+                    } else {
+                        continue;
+                    };
+                    match res {
+                        Ok(()) => println!("{YELLOW}REVERTED{NC}: {}", path.display()),
+                        Err(e) => failures.push(format!("'{}' not removed ({e})", path.display())),
+                    }
+                }
+                Step::MovedAside {
+                    original, staged, ..
+                } => match move_path(&staged, &original) {
+                    Ok(()) => println!("{YELLOW}RESTORED{NC}: {}", original.display()),
+                    Err(e) => failures.push(format!(
+                        "'{}' not put back from '{}' ({e})",
+                        original.display(),
+                        staged.display()
+                    )),
+                },
+            }
+        }
+        if failures.is_empty() {
+            if staging.exists() {
+                std::fs::remove_dir_all(staging)?;
+            }
+            Ok(())
+        } else {
+            // what could not be put back is still in the staging directory
+            for failure in &failures {
+                println!("{RED}ERROR{NC}: Rolling back: {failure}");
+            }
+            Err(anyhow!(
+                "Rolling back failed, {} modification(s) could not be undone: {}",
+                failures.len(),
+                failures.join(", ")
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::temp_dir;
+
+    fn dirs(root: &Path) -> InstallDirs {
+        InstallDirs {
+            bin: root.join("bin"),
+            config: root.join("config"),
+            data: root.join("data"),
+            state: root.join("state"),
+            root: None,
+        }
+    }
+
+    #[test]
+    fn rollback_restores_the_tree_and_keeps_the_error() {
+        let root = temp_dir("transaction-rollback");
+        let dirs = dirs(&root);
+        let (created, kept) = (root.join("created"), root.join("kept"));
+        std::fs::write(&kept, "user file").unwrap();
+        let mut staging = PathBuf::new();
+
+        let res: Result<()> = Transaction::run(&dirs, |tx| {
+            tx.create(&created, || Ok(std::fs::write(&created, "new")?))?;
+            tx.move_aside(&kept)?;
+            staging = tx.get_staging_dir();
+            Err(anyhow!("installation failed"))
+        });
+        assert_eq!(res.unwrap_err().to_string(), "installation failed");
+        assert!(!created.exists());
+        assert_eq!(std::fs::read_to_string(&kept).unwrap(), "user file");
+        assert!(staging.starts_with(&dirs.state) && !staging.exists());
+    }
+
+    #[test]
+    fn rollback_removes_what_a_failed_create_left_but_not_what_was_there() {
+        let root = temp_dir("transaction-create");
+        let dirs = dirs(&root);
+        let (partial, existing) = (root.join("partial"), root.join("existing"));
+        std::fs::write(&existing, "user file").unwrap();
+
+        let res: Result<()> = Transaction::run(&dirs, |tx| {
+            tx.create(&partial, || {
+                std::fs::create_dir(&partial)?;
+                Err(anyhow!("copy interrupted"))
+            })?;
+            Ok(())
+        });
+        assert!(res.is_err());
+        assert!(!partial.exists());
+
+        let res: Result<()> = Transaction::run(&dirs, |tx| {
+            tx.create(&existing, || Err(anyhow!("'existing' already exists")))
+        });
+        assert!(res.is_err());
+        assert_eq!(std::fs::read_to_string(&existing).unwrap(), "user file");
+    }
+
+    #[test]
+    fn failures_of_the_rollback_are_attached_to_the_error() {
+        let root = temp_dir("transaction-failure");
+        let dirs = dirs(&root);
+        let kept = root.join("kept");
+        std::fs::write(&kept, "user file").unwrap();
+
+        let res: Result<()> = Transaction::run(&dirs, |tx| {
+            tx.move_aside(&kept)?;
+            // the original cannot be put back once the path is taken by a directory
+            std::fs::create_dir_all(kept.join("taken"))?;
+            Err(anyhow!("installation failed"))
+        });
+        let message = format!("{:#}", res.unwrap_err());
+        assert!(message.contains("1 modification(s) could not be undone"));
+        assert!(message.ends_with("installation failed"));
+    }
+
+    #[test]
+    fn commit_saves_backups_and_drops_the_staging_directory() {
+        let root = temp_dir("transaction-commit");
+        let dirs = dirs(&root);
+        let (replaced, moved) = (root.join("replaced"), root.join("moved"));
+        std::fs::write(&replaced, "user config").unwrap();
+        std::fs::write(&moved, "old link").unwrap();
+        let mut staging = PathBuf::new();
+
+        Transaction::run(&dirs, |tx| {
+            tx.set_key("tool");
+            tx.back_up(&replaced)?;
+            tx.move_aside(&moved)?;
+            tx.create(&replaced, || Ok(std::fs::write(&replaced, "installed")?))?;
+            staging = tx.get_staging_dir();
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(std::fs::read_to_string(&replaced).unwrap(), "installed");
+        assert!(!moved.exists() && !staging.exists());
+
+        assert_eq!(Backup::keys(&dirs).unwrap(), ["tool"]);
+        let backups = Backup::list(&dirs, "tool").unwrap();
+        assert_eq!(backups.len(), 1);
+        let entry = &backups[0].files[..];
+        assert_eq!(entry.len(), 1);
+        assert_eq!(entry[0].original, replaced);
+        let saved = backups[0].dir.join(&entry[0].saved);
+        assert_eq!(std::fs::read_to_string(saved).unwrap(), "user config");
+    }
+}
@@ -1,4 +1,6 @@
 use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+use std::ffi::OsString;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
@@ -12,64 +14,87 @@ pub const YELLOW: &str = "\x1b[0;33m";
 pub const BLUE: &str = "\x1b[0;34m";
 pub const NC: &str = "\x1b[0m";
 
-pub fn get_home_dir() -> PathBuf {
-    dirs::home_dir().unwrap_or(PathBuf::from("~"))
+/// Directories packages are installed in, resolved once from the configuration and the
+/// arguments
+#[derive(Debug, Default, Clone)]
+pub struct InstallDirs {
+    pub bin: PathBuf,
+    pub config: PathBuf,
+    pub data: PathBuf,
+    /// Where the manifest, backups and env scripts are kept, the data directory unless
+    /// installing for every user
+    pub state: PathBuf,
+    /// Directory everything is written under, as DESTDIR when packaging
+    pub root: Option<PathBuf>,
 }
 
-pub fn get_config_dir() -> PathBuf {
-    dirs::config_dir().unwrap_or(PathBuf::from("~/.config/"))
-}
-
-pub fn get_bin_dir() -> PathBuf {
-    dirs::executable_dir().unwrap_or(PathBuf::from("~/.local/bin/"))
-}
-
-pub fn get_data_dir() -> PathBuf {
-    dirs::data_dir().unwrap_or(PathBuf::from("~/.local/share/"))
-}
-
-pub fn create_dirs() -> Result<()> {
-    if !get_config_dir().exists() {
-        std::fs::create_dir_all(get_config_dir())?;
+impl InstallDirs {
+    /// The given directories, the defaults of the platform for the missing ones
+    pub fn new(
+        bin: Option<PathBuf>,
+        config: Option<PathBuf>,
+        data: Option<PathBuf>,
+    ) -> Result<InstallDirs> {
+        let pick = |dir: Option<PathBuf>, home_relative: &str| -> Result<PathBuf> {
+            match dir {
+                Some(dir) => Ok(dir),
+                None => Ok(get_home_dir()?.join(home_relative)),
+            }
+        };
+        let data = pick(data.or_else(dirs::data_dir), ".local/share")?;
+        Ok(InstallDirs {
+            bin: pick(bin.or_else(dirs::executable_dir), ".local/bin")?,
+            config: pick(config.or_else(dirs::config_dir), ".config")?,
+            state: data.clone(),
+            data,
+            root: None,
+        })
     }
-    if !get_bin_dir().exists() {
-        std::fs::create_dir_all(get_bin_dir())?;
+
+    pub fn is_staged(&self) -> bool {
+        self.root.is_some()
     }
-    if !get_data_dir().exists() {
-        std::fs::create_dir_all(get_data_dir())?;
+
+    /// Where `path` actually is on disk, moved under the staging root if there is one
+    pub fn staged<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let path = path.as_ref();
+        match self.root.as_ref() {
+            Some(root) => root.join(path.strip_prefix("/").unwrap_or(path)),
+            None => path.to_path_buf(),
+        }
     }
-    Ok(())
-}
 
-pub fn get_shell_config_path() -> PathBuf {
-    if let Ok(shell) = std::env::var("SHELL") {
-        if let Some(basename) = PathBuf::from(shell).file_name() {
-            return get_home_dir().join(format!(".{}rc", basename.to_string_lossy()));
+    pub fn create(&self) -> Result<()> {
+        for dir in [&self.config, &self.bin, &self.data, &self.state].map(|dir| self.staged(dir)) {
+            if !dir.exists() {
+                std::fs::create_dir_all(dir)?;
+            }
         }
+        Ok(())
     }
-    get_home_dir().join(".bashrc")
-}
 
-pub fn check_path() -> bool {
-    if let Some(dir) = get_bin_dir().to_str() {
-        std::env::var("PATH").is_ok_and(|path| path.contains(dir))
-    } else {
-        false
+    /// Fail unless the directories, or the ancestors they will be created in, can be
+    /// written to
+    pub fn check_writable(&self) -> Result<()> {
+        for dir in [&self.config, &self.bin, &self.data, &self.state].map(|dir| self.staged(dir)) {
+            let Some(existing) = dir.ancestors().find(|d| d.exists()) else {
+                continue;
+            };
+            let probe = existing.join(format!(".editor-write-check-{}", std::process::id()));
+            if std::fs::File::create(&probe).is_err() {
+                return Err(anyhow!(
+                    "'{}' is not writable, run as a user allowed to install there",
+                    dir.display()
+                ));
+            }
+            std::fs::remove_file(probe)?;
+        }
+        Ok(())
     }
 }
 
-// retunr true if added to PATH and false otherwise
-pub fn export_bin_dir() -> Result<bool> {
-    if !check_path() {
-        let mut config = std::fs::OpenOptions::new()
-            .append(true)
-            .open(get_shell_config_path())?;
-        let export = format!("export PATH=\"{}:$PATH\"\n", get_bin_dir().display());
-        config.write_all(export.as_bytes())?;
-        Ok(true)
-    } else {
-        Ok(false)
-    }
+pub fn get_home_dir() -> Result<PathBuf> {
+    dirs::home_dir().ok_or(anyhow!("The home directory of the user cannot be found"))
 }
 
 pub fn iter_includes<P, V, U>(owner: V, includer: U) -> bool
@@ -91,6 +116,56 @@ pub fn make_absolute<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
     }
 }
 
+// expand `~` and `$VAR` / `${VAR}`, then make the path absolute relatively to `base`
+pub fn expand_path<P: AsRef<Path>, B: AsRef<Path>>(path: P, base: B) -> Result<PathBuf> {
+    let path = path.as_ref();
+    let Some(raw) = path.to_str() else {
+        return Ok(base.as_ref().join(path));
+    };
+
+    let mut expanded = String::with_capacity(raw.len());
+    let mut rest = raw;
+    if rest == "~" || rest.starts_with("~/") {
+        expanded.push_str(&get_home_dir()?.to_string_lossy());
+        rest = &rest[1..];
+    }
+    while let Some(index) = rest.find('$') {
+        expanded.push_str(&rest[..index]);
+        rest = &rest[index + 1..];
+        let (name, next) = if let Some(braced) = rest.strip_prefix('{') {
+            let end = braced.find('}').ok_or(anyhow!("Missing '}}' in '{raw}'"))?;
+            (&braced[..end], &braced[end + 1..])
+        } else {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+        if name.is_empty() {
+            expanded.push('$');
+        } else {
+            expanded.push_str(&std::env::var(name).map_err(|_| {
+                anyhow!("Environment variable '{name}' used in '{raw}' is not set")
+            })?);
+        }
+        rest = next;
+    }
+    expanded.push_str(rest);
+
+    // remove `.` and `..` so that paths can be compared
+    let mut result = PathBuf::new();
+    for component in base.as_ref().join(expanded).components() {
+        match component {
+            std::path::Component::CurDir => (),
+            std::path::Component::ParentDir => {
+                result.pop();
+            }
+            c => result.push(c),
+        }
+    }
+    Ok(result)
+}
+
 pub fn find_common_path<P: AsRef<Path>>(one: P, two: P) -> Result<PathBuf> {
     let mut result = PathBuf::new();
     let one = make_absolute(one)?;
@@ -124,6 +199,107 @@ pub fn find_relative_path<P: AsRef<Path>>(from: P, to: P) -> Result<PathBuf> {
     Ok(from.strip_prefix(to)?.to_path_buf())
 }
 
+fn sorted_entries(dir: &Path) -> Result<Vec<OsString>> {
+    let mut names = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.file_name()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    names.sort();
+    Ok(names)
+}
+
+// return true if both paths contain the same files with the same content
+pub fn same_content<P: AsRef<Path>, Q: AsRef<Path>>(one: P, two: Q) -> Result<bool> {
+    let (one, two) = (one.as_ref(), two.as_ref());
+    if one.is_dir() {
+        if !two.is_dir() {
+            return Ok(false);
+        }
+        let names = sorted_entries(one)?;
+        if names != sorted_entries(two)? {
+            return Ok(false);
+        }
+        for name in names {
+            if !same_content(one.join(&name), two.join(&name))? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    } else if two.is_file() {
+        Ok(std::fs::read(one)? == std::fs::read(two)?)
+    } else {
+        Ok(false)
+    }
+}
+
+// sha256 of a file or of a whole directory (names and contents)
+pub fn hash_path<P: AsRef<Path>>(path: P) -> Result<String> {
+    fn hash_rec(path: &Path, relative: &Path, hasher: &mut Sha256) -> Result<()> {
+        if path.is_dir() {
+            for name in sorted_entries(path)? {
+                let relative = relative.join(&name);
+                hasher.update(relative.to_string_lossy().as_bytes());
+                hash_rec(&path.join(&name), &relative, hasher)?;
+            }
+        } else {
+            hasher.update(std::fs::read(path)?);
+        }
+        Ok(())
+    }
+
+    let mut hasher = Sha256::new();
+    hash_rec(path.as_ref(), Path::new(""), &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// rename falling back on copy and remove when crossing filesystems
+pub fn move_path<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> Result<()> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    copy_path(from, to)?;
+    if from.is_dir() && !from.is_symlink() {
+        std::fs::remove_dir_all(from)?;
+    } else {
+        std::fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+// copy a file, a directory or a symbolic link as is
+pub fn copy_path<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> Result<()> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+    if from.is_symlink() {
+        std::os::unix::fs::symlink(std::fs::read_link(from)?, to)?;
+    } else if from.is_dir() {
+        copy_dir::copy_dir(from, to)?;
+    } else {
+        std::fs::copy(from, to)?;
+    }
+    Ok(())
+}
+
+// seconds since epoch as "YYYY-MM-DD HH:MM:SS UTC"
+pub fn format_timestamp(timestamp: u64) -> String {
+    let (days, secs) = ((timestamp / 86400) as i64, timestamp % 86400);
+    // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
 pub fn prompt(message: &str) -> Result<String> {
     print!("{message}");
     let mut choice = String::new();
@@ -138,3 +314,106 @@ pub fn existence(path: &PathBuf) -> Result<String> {
         Ok(format!("({RED}NOT FOUND{NC})"))
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Empty directory of the system temporary directory, unique to the test `name`
+    pub(crate) fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("editor-{}-{name}", std::process::id()));
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_tree(dir: &Path, files: &[(&str, &str)]) {
+        for (name, content) in files {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+    }
+
+    #[test]
+    fn expand_path_resolves_relative_home_and_variables() {
+        let base = Path::new("/base/dir");
+        assert_eq!(
+            expand_path("bin/tool", base).unwrap(),
+            PathBuf::from("/base/dir/bin/tool")
+        );
+        assert_eq!(
+            expand_path("/abs/./tool", base).unwrap(),
+            PathBuf::from("/abs/tool")
+        );
+        assert_eq!(
+            expand_path("../other/tool", base).unwrap(),
+            PathBuf::from("/base/other/tool")
+        );
+        assert_eq!(
+            expand_path("~/tool", base).unwrap(),
+            get_home_dir().unwrap().join("tool")
+        );
+
+        std::env::set_var("EDITOR_TEST_EXPAND", "/from/var");
+        assert_eq!(
+            expand_path("$EDITOR_TEST_EXPAND/tool", base).unwrap(),
+            PathBuf::from("/from/var/tool")
+        );
+        assert_eq!(
+            expand_path("${EDITOR_TEST_EXPAND}_suffix", base).unwrap(),
+            PathBuf::from("/from/var_suffix")
+        );
+        // a lone `$` is kept
+        assert_eq!(
+            expand_path("price$/tool", base).unwrap(),
+            PathBuf::from("/base/dir/price$/tool")
+        );
+        assert!(expand_path("$EDITOR_TEST_UNSET_VARIABLE/tool", base).is_err());
+        assert!(expand_path("${EDITOR_TEST_EXPAND/tool", base).is_err());
+    }
+
+    #[test]
+    fn same_content_compares_files_and_directories() {
+        let dir = temp_dir("same-content");
+        let (one, two) = (dir.join("one"), dir.join("two"));
+        write_tree(&one, &[("a", "a"), ("sub/b", "b")]);
+        write_tree(&two, &[("a", "a"), ("sub/b", "b")]);
+        assert!(same_content(&one, &two).unwrap());
+        assert!(same_content(one.join("a"), two.join("a")).unwrap());
+
+        std::fs::write(two.join("sub/b"), "changed").unwrap();
+        assert!(!same_content(&one, &two).unwrap());
+        std::fs::write(two.join("sub/b"), "b").unwrap();
+        std::fs::write(two.join("extra"), "").unwrap();
+        assert!(!same_content(&one, &two).unwrap());
+        // a file never matches a directory
+        assert!(!same_content(one.join("a"), two.join("sub")).unwrap());
+        assert!(!same_content(one.join("a"), dir.join("missing")).unwrap());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn hash_path_depends_on_names_and_contents() {
+        let dir = temp_dir("hash-path");
+        let (one, two) = (dir.join("one"), dir.join("two"));
+        write_tree(&one, &[("a", "a"), ("sub/b", "b")]);
+        write_tree(&two, &[("a", "a"), ("sub/b", "b")]);
+        assert_eq!(hash_path(&one).unwrap(), hash_path(&two).unwrap());
+        // sha256 of "a"
+        assert_eq!(
+            hash_path(one.join("a")).unwrap(),
+            "ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb"
+        );
+
+        std::fs::rename(two.join("a"), two.join("c")).unwrap();
+        assert_ne!(hash_path(&one).unwrap(), hash_path(&two).unwrap());
+        std::fs::rename(two.join("c"), two.join("a")).unwrap();
+        std::fs::write(two.join("a"), "changed").unwrap();
+        assert_ne!(hash_path(&one).unwrap(), hash_path(&two).unwrap());
+        assert!(hash_path(dir.join("missing")).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}